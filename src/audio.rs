@@ -0,0 +1,296 @@
+use std::sync::Arc;
+use realfft::{ RealFftPlanner, RealToComplex };
+
+use crate::error::Error;
+
+// samples per analysis window, gives 1024 bins of which the first 512 end up in the texture
+const FFT_SIZE: usize = 2048;
+// width of the audio texture, like Shadertoy's sound input
+const TEXTURE_WIDTH: usize = 512;
+// samples between the precomputed band energies
+const HOP: usize = 512;
+// spectrum in decibels mapped to [0, 1] like WebAudio's analyser does
+const MIN_DB: f32 = -100.0;
+const MAX_DB: f32 = -30.0;
+// frequency ranges of bass, mid and treble in Hz
+const BANDS: [(f32, f32); 3] = [(20.0, 250.0), (250.0, 4000.0), (4000.0, 16000.0)];
+// a beat is bass this much louder than the second before, at least this far apart
+const BEAT_THRESHOLD: f32 = 1.4;
+const BEAT_GAP: f32 = 0.25;
+// how fast iBeat fades after a beat, per second
+const BEAT_DECAY: f32 = 8.0;
+/// iSampleRate when no audio is loaded
+pub(crate) const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// A decoded audio file with its analysis.
+/// Everything is a function of the time, so rendering to file gives the same result every time.
+pub(crate) struct Audio{
+    samples: Vec<f32>,
+    sample_rate: u32,
+    fft: Arc<dyn RealToComplex<f32>>,
+    // bass, mid and treble every HOP samples, normalized to [0, 1] over the whole file
+    bands: Vec<[f32; 3]>,
+    beats: Vec<f32>,
+}
+
+impl Audio{
+    /// Decodes a WAV file, mixed down to mono
+    pub fn load(path: &str) -> Result<Self, Error>{
+        let mut reader = hound::WavReader::open(path).map_err(|e| match e{
+            hound::Error::IoError(e) => Error::io(path, e),
+            e => Error::parse(path, e),
+        })?;
+        let spec = reader.spec();
+        let interleaved: Vec<f32> = match spec.sample_format{
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader.samples::<i32>().map(|s| s.map(|s| s as f32 * scale)).collect::<Result<_, _>>()
+            },
+        }.map_err(|e| Error::parse(path, e))?;
+        let channels = spec.channels.max(1) as usize;
+        let samples = interleaved.chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        Ok(Self::new(samples, spec.sample_rate))
+    }
+
+    pub fn new(samples: Vec<f32>, sample_rate: u32) -> Self{
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+        let mut audio = Self{ samples, sample_rate, fft, bands: Vec::new(), beats: Vec::new() };
+        audio.analyze();
+        audio
+    }
+
+    // band energies and beats of the whole file
+    fn analyze(&mut self){
+        let bin_hz = self.sample_rate as f32 / FFT_SIZE as f32;
+        let mut max = [f32::EPSILON; 3];
+        for i in 0..=self.samples.len() / HOP{
+            let magnitudes = self.magnitudes(i * HOP);
+            let mut energies = [0.0; 3];
+            for (energy, (low, high)) in energies.iter_mut().zip(BANDS){
+                // bands above Nyquist at low sample rates are empty, with energy 0
+                let end = ((high / bin_hz) as usize).min(magnitudes.len());
+                let bins = &magnitudes[((low / bin_hz) as usize).min(end)..end];
+                *energy = (bins.iter().map(|m| m * m).sum::<f32>() / bins.len().max(1) as f32).sqrt();
+            }
+            for (max, energy) in max.iter_mut().zip(energies){
+                *max = max.max(energy);
+            }
+            self.bands.push(energies);
+        }
+        for energies in &mut self.bands{
+            for (energy, max) in energies.iter_mut().zip(max){
+                *energy /= max;
+            }
+        }
+        let second = (self.sample_rate as usize / HOP).max(1);
+        let mut last_beat = f32::NEG_INFINITY;
+        for i in 1..self.bands.len(){
+            let history = &self.bands[i.saturating_sub(second)..i];
+            let average = history.iter().map(|b| b[0]).sum::<f32>() / history.len() as f32;
+            let bass = self.bands[i][0];
+            let time = (i * HOP) as f32 / self.sample_rate as f32;
+            if bass > average * BEAT_THRESHOLD && bass > self.bands[i - 1][0] && time - last_beat >= BEAT_GAP{
+                self.beats.push(time);
+                last_beat = time;
+            }
+        }
+    }
+
+    // magnitudes of the spectrum of the window ending at a sample, silence outside the file
+    fn magnitudes(&self, end: usize) -> Vec<f32>{
+        let mut input = self.fft.make_input_vec();
+        let start = end as isize - FFT_SIZE as isize;
+        for (i, x) in input.iter_mut().enumerate(){
+            let sample = usize::try_from(start + i as isize).ok()
+                .and_then(|s| self.samples.get(s))
+                .copied()
+                .unwrap_or(0.0);
+            *x = sample * blackman(i);
+        }
+        let mut output = self.fft.make_output_vec();
+        self.fft.process(&mut input, &mut output).expect("fft buffers have the planned size");
+        output.iter().map(|c| c.norm() / FFT_SIZE as f32).collect()
+    }
+
+    fn sample_at(&self, time: f32) -> usize{
+        (time.max(0.0) * self.sample_rate as f32) as usize
+    }
+
+    /// The spectrum in the first row and the waveform in the second, both in [0, 1]
+    pub fn texture_data(&self, time: f32) -> Vec<u8>{
+        let end = self.sample_at(time);
+        let mut data = Vec::with_capacity(TEXTURE_WIDTH * 2);
+        for magnitude in &self.magnitudes(end)[..TEXTURE_WIDTH]{
+            let db = 20.0 * magnitude.max(1e-10).log10();
+            data.push((((db - MIN_DB) / (MAX_DB - MIN_DB)).clamp(0.0, 1.0) * 255.0) as u8);
+        }
+        let start = end as isize - TEXTURE_WIDTH as isize;
+        for i in 0..TEXTURE_WIDTH{
+            let sample = usize::try_from(start + i as isize).ok()
+                .and_then(|s| self.samples.get(s))
+                .copied()
+                .unwrap_or(0.0);
+            data.push(((sample * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0) as u8);
+        }
+        data
+    }
+
+    pub fn sample_rate(&self) -> u32{
+        self.sample_rate
+    }
+
+    /// Energy of bass, mid and treble, each normalized to [0, 1] over the whole file
+    pub fn bands(&self, time: f32) -> [f32; 3]{
+        if time < 0.0{
+            return [0.0; 3];
+        }
+        let i = (self.sample_at(time) as f32 / HOP as f32).round() as usize;
+        self.bands.get(i).copied().unwrap_or([0.0; 3])
+    }
+
+    /// 1 on a beat, fading out until the next one
+    pub fn beat(&self, time: f32) -> f32{
+        let last = self.beats.iter().take_while(|beat| **beat <= time).last();
+        last.map_or(0.0, |last| (-(time - last) * BEAT_DECAY).exp())
+    }
+}
+
+// window against leakage between bins, like WebAudio uses
+fn blackman(i: usize) -> f32{
+    let x = std::f32::consts::TAU * i as f32 / FFT_SIZE as f32;
+    0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
+
+/// The audio texture, updated every frame for the time
+pub(crate) struct AudioTexture{
+    audio: Arc<Audio>,
+    id: gl::types::GLuint,
+}
+
+impl AudioTexture{
+    pub fn new(audio: Arc<Audio>) -> Self{
+        let mut id: gl::types::GLuint = 0;
+        unsafe{
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D, id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        }
+        Self{ audio, id }
+    }
+
+    pub fn sample_rate(&self) -> u32{
+        self.audio.sample_rate()
+    }
+
+    /// Upload the spectrum and waveform at a time
+    pub fn update(&self, time: f32){
+        let data = self.audio.texture_data(time);
+        unsafe{
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D, 0, gl::R8 as i32, TEXTURE_WIDTH as i32, 2, 0,
+                gl::RED, gl::UNSIGNED_BYTE, data.as_ptr() as *const gl::types::GLvoid
+            );
+        }
+    }
+}
+
+impl Drop for AudioTexture{
+    fn drop(&mut self){
+        unsafe{
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 44100;
+
+    // a 100 Hz kick every half second over quiet 5 kHz noise
+    fn kicks(seconds: f32) -> Vec<f32>{
+        (0..(seconds * RATE as f32) as usize).map(|i| {
+            let t = i as f32 / RATE as f32;
+            let kick = if t % 0.5 < 0.1 { (t * 100.0 * std::f32::consts::TAU).sin() } else { 0.0 };
+            kick + 0.05 * (t * 5000.0 * std::f32::consts::TAU).sin()
+        }).collect()
+    }
+
+    #[test]
+    fn bands_and_beats() {
+        let audio = Audio::new(kicks(3.0), RATE);
+        let [bass, mid, treble] = audio.bands(1.05);
+        assert!(bass > 0.9 && mid < 0.1);
+        // the noise is as loud all the time
+        assert!(treble > 0.9);
+        assert!(audio.bands(1.3)[0] < 0.1);
+        assert!(audio.beats.iter().all(|beat| beat % 0.5 < 0.05));
+        assert!(audio.beats.len() >= 4);
+        assert!(audio.beat(1.03) > audio.beat(1.2));
+        assert_eq!(audio.bands(-1.0), [0.0; 3]);
+        assert_eq!(audio.bands(10.0), [0.0; 3]);
+    }
+
+    #[test]
+    fn texture_rows() {
+        let audio = Audio::new(kicks(1.0), RATE);
+        let data = audio.texture_data(0.05);
+        assert_eq!(data.len(), TEXTURE_WIDTH * 2);
+        // 100 Hz is bin 4 or 5, far above 2 kHz in bin 93
+        assert!(data[4].max(data[5]) > 200);
+        assert!(data[93] < data[4].max(data[5]));
+        // silence is in the middle of the waveform
+        assert_eq!(Audio::new(vec![0.0; 4096], RATE).texture_data(0.05)[TEXTURE_WIDTH..], [127; TEXTURE_WIDTH]);
+        // deterministic
+        assert_eq!(data, audio.texture_data(0.05));
+    }
+
+    #[test]
+    fn load_wav() {
+        let path = crate::test_dir("load_wav").join("audio.wav");
+        let spec = hound::WavSpec{
+            channels: 2, sample_rate: RATE, bits_per_sample: 16, sample_format: hound::SampleFormat::Int
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..100{
+            writer.write_sample(i16::MAX).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let audio = Audio::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(audio.samples.len(), 100);
+        assert!((audio.samples[0] - 0.5).abs() < 0.001);
+        assert!(matches!(Audio::load("does_not_exist.wav"), Err(Error::Io{ .. })));
+        std::fs::write(&path, "not a wav").unwrap();
+        assert!(matches!(Audio::load(path.to_str().unwrap()), Err(Error::Parse{ .. })));
+    }
+
+    #[test]
+    fn low_sample_rate() {
+        // Nyquist is below the treble band
+        let rate = 5512;
+        let path = crate::test_dir("low_sample_rate").join("audio.wav");
+        let spec = hound::WavSpec{
+            channels: 1, sample_rate: rate, bits_per_sample: 16, sample_format: hound::SampleFormat::Int
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..rate{
+            let t = i as f32 / rate as f32;
+            writer.write_sample(((t * 100.0 * std::f32::consts::TAU).sin() * 10000.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let audio = Audio::load(path.to_str().unwrap()).unwrap();
+        let [bass, _, treble] = audio.bands(0.5);
+        assert!(bass > 0.9);
+        assert_eq!(treble, 0.0);
+    }
+}
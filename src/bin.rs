@@ -13,6 +13,7 @@ fn main(){
         .with_canvas_width(320 * mul)
         .with_canvas_height(180 * mul)
         .with_pixelate(true)
+        .with_stdin_control(true)
         .with_streamer(streamer)
         .run_live().expect("Could not run.");
         // .into_ffmpeg_renderer()
@@ -0,0 +1,286 @@
+use std::time::{ Duration, Instant };
+
+// playback speeds are powers of two between these
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 4.0;
+// a frame when stepping without a fixed timestep
+const STEP_TIME: f32 = 1.0 / 60.0;
+
+/// Time of a frame with a fixed timestep, the same live and when rendering to file
+pub(crate) fn time_of_frame(frame: i64, framerate: u32) -> f32{
+    frame as f32 * (1.0 / framerate as f32)
+}
+
+/// Play time in live mode.
+/// Follows the wall clock, or with a fixed timestep advances exactly one frame time every frame
+/// like rendering to video does, so a frame gets the same iTime live as in the video.
+/// Plays faster, slower or in reverse, and can loop a region.
+#[derive(Debug)]
+pub(crate) struct Clock{
+    framerate: Option<u32>,
+    frame_time: Option<f32>,
+    throttle: bool,
+    time: f32,
+    // with a fixed timestep the time is step * frame_time, so it does not drift
+    step: i64,
+    last: Instant,
+    // negative plays in reverse
+    speed: f32,
+    loop_in: Option<f32>,
+    loop_out: Option<f32>,
+}
+
+impl Clock{
+    pub fn new(framerate: Option<u32>, throttle: bool) -> Self{
+        Self{
+            framerate,
+            frame_time: framerate.map(|framerate| 1.0 / framerate as f32),
+            throttle,
+            time: 0.0,
+            step: 0,
+            last: Instant::now(),
+            speed: 1.0,
+            loop_in: None,
+            loop_out: None,
+        }
+    }
+
+    pub fn time(&self) -> f32{
+        self.time
+    }
+
+    /// With a fixed timestep the number of the frame at the current time, like iFrame when
+    /// rendering to file. Before 0 it is 0.
+    pub fn frame(&self) -> Option<u32>{
+        self.framerate.map(|_| self.step.max(0) as u32)
+    }
+
+    /// Frames per second of the fixed timestep
+    pub fn framerate(&self) -> Option<f32>{
+        self.framerate.map(|framerate| framerate as f32)
+    }
+
+    /// Go to the next frame and return the time step, negative in reverse.
+    /// With a throttled fixed timestep this waits until the frame is due in real time,
+    /// so the speed changes how often a frame is due instead of the time step.
+    pub fn advance(&mut self) -> f32{
+        let dt = match self.frame_time{
+            Some(frame_time) => {
+                if self.throttle{
+                    let due = self.last + Duration::from_secs_f32(frame_time / self.speed.abs());
+                    let now = Instant::now();
+                    if due > now{
+                        std::thread::sleep(due - now);
+                    }
+                    // when falling behind, slow down instead of catching up with a burst of frames
+                    self.last = due.max(now);
+                }
+                let dir = self.speed.signum() as i64;
+                self.step += dir;
+                self.time = time_of_frame(self.step, self.framerate.expect("fixed timestep"));
+                dir as f32 * frame_time
+            },
+            None => {
+                let now = Instant::now();
+                let dt = (now - self.last).as_secs_f32() * self.speed;
+                self.last = now;
+                self.time += dt;
+                dt
+            },
+        };
+        // wrap around when playing out of the loop region
+        match self.loop_region(){
+            Some((start, end)) if self.speed > 0.0 && self.time > end => self.seek(start),
+            Some((start, end)) if self.speed < 0.0 && self.time < start => self.seek(end),
+            _ => (),
+        }
+        dt
+    }
+
+    /// Jump to a time, with a fixed timestep to the nearest frame
+    pub fn seek(&mut self, time: f32){
+        match self.frame_time{
+            Some(frame_time) => {
+                self.step = (time / frame_time).round() as i64;
+                self.time = time_of_frame(self.step, self.framerate.expect("fixed timestep"));
+            },
+            None => self.time = time,
+        }
+        self.sync();
+    }
+
+    pub fn shift(&mut self, dt: f32){
+        self.seek(self.time + dt);
+    }
+
+    /// Go one frame forward or back
+    pub fn step(&mut self, frames: i64){
+        self.shift(frames as f32 * self.frame_time.unwrap_or(STEP_TIME));
+    }
+
+    pub fn speed(&self) -> f32{
+        self.speed
+    }
+
+    /// Double the speed, up to 4x
+    pub fn faster(&mut self){
+        self.speed = (self.speed.abs() * 2.0).min(MAX_SPEED).copysign(self.speed);
+    }
+
+    /// Halve the speed, down to 0.25x
+    pub fn slower(&mut self){
+        self.speed = (self.speed.abs() / 2.0).max(MIN_SPEED).copysign(self.speed);
+    }
+
+    pub fn reverse(&mut self){
+        self.speed = -self.speed;
+    }
+
+    /// Start the loop region at the current time
+    pub fn set_loop_in(&mut self){
+        self.loop_in = Some(self.time);
+    }
+
+    /// End the loop region at the current time
+    pub fn set_loop_out(&mut self){
+        self.loop_out = Some(self.time);
+    }
+
+    pub fn set_loop(&mut self, start: f32, end: f32){
+        self.loop_in = Some(start);
+        self.loop_out = Some(end);
+    }
+
+    pub fn clear_loop(&mut self){
+        self.loop_in = None;
+        self.loop_out = None;
+    }
+
+    /// The loop region, once both ends are set and in the right order
+    pub fn loop_region(&self) -> Option<(f32, f32)>{
+        match (self.loop_in, self.loop_out){
+            (Some(start), Some(end)) if start < end => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    /// Forget the real time that passed, like while paused
+    pub fn sync(&mut self){
+        self.last = Instant::now();
+    }
+}
+
+/// Parses a time typed by the user, in seconds like "12.5" or minutes and seconds like "1:02.5"
+pub(crate) fn parse_time(text: &str) -> Option<f32>{
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-'){
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (minutes, seconds) = match text.split_once(':'){
+        Some((minutes, seconds)) => (minutes.parse::<u32>().ok()?, seconds),
+        None => (0, text),
+    };
+    let seconds = seconds.parse::<f32>().ok().filter(|s| s.is_finite() && *s >= 0.0)?;
+    let time = minutes as f32 * 60.0 + seconds;
+    Some(if negative { -time } else { time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timestep() {
+        let mut clock = Clock::new(Some(30), false);
+        // the same time as rendering computes for the frame
+        for frame in 1..=300{
+            assert_eq!(clock.advance(), 1.0 / 30.0);
+            assert_eq!(clock.time(), frame as f32 * (1.0 / 30.0));
+        }
+        clock.seek(1.01);
+        assert_eq!(clock.time(), 30.0 * (1.0 / 30.0));
+        clock.shift(-2.0);
+        assert_eq!(clock.time(), -30.0 * (1.0 / 30.0));
+        assert_eq!(clock.framerate(), Some(30.0));
+    }
+
+    #[test]
+    fn throttled() {
+        let mut clock = Clock::new(Some(100), true);
+        let start = Instant::now();
+        for _ in 0..10{
+            clock.advance();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        let mut clock = Clock::new(None, false);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(clock.advance() >= 0.02);
+        assert_eq!(clock.framerate(), None);
+    }
+
+    #[test]
+    fn speed_and_loop() {
+        let mut clock = Clock::new(Some(10), false);
+        for _ in 0..4{
+            clock.slower();
+        }
+        assert_eq!(clock.speed(), 0.25);
+        clock.reverse();
+        for _ in 0..5{
+            clock.faster();
+        }
+        assert_eq!(clock.speed(), -4.0);
+        // stepping in reverse and wrapping to the end of the loop
+        clock.seek(1.0);
+        clock.set_loop_in();
+        clock.step(5);
+        clock.set_loop_out();
+        assert_eq!(clock.loop_region(), Some((1.0, 1.5)));
+        clock.step(-4);
+        assert_eq!(clock.advance(), -0.1);
+        assert_eq!(clock.time(), 1.0);
+        clock.advance();
+        assert_eq!(clock.time(), 1.5);
+        // forward wraps to the start
+        clock.reverse();
+        clock.advance();
+        assert_eq!(clock.time(), 1.0);
+        clock.clear_loop();
+        assert_eq!(clock.loop_region(), None);
+    }
+
+    #[test]
+    fn frames_like_rendering() {
+        // iFrame and iTime are those of the rendered frame at that time, whatever got there
+        let mut clock = Clock::new(Some(30), false);
+        let rendered = |clock: &Clock, frame: u32| {
+            assert_eq!(clock.frame(), Some(frame));
+            assert_eq!(clock.time(), time_of_frame(frame as i64, 30));
+        };
+        for _ in 0..10{
+            clock.advance();
+        }
+        rendered(&clock, 10);
+        clock.seek(2.01);
+        rendered(&clock, 60);
+        clock.set_loop(1.0, 2.0);
+        clock.advance();
+        rendered(&clock, 30);
+        clock.step(-2);
+        rendered(&clock, 28);
+        clock.shift(-5.0);
+        assert_eq!(clock.frame(), Some(0));
+        assert_eq!(Clock::new(None, false).frame(), None);
+    }
+
+    #[test]
+    fn typed_time() {
+        assert_eq!(parse_time("12.5"), Some(12.5));
+        assert_eq!(parse_time(" 1:02.5\n"), Some(62.5));
+        assert_eq!(parse_time("-3"), Some(-3.0));
+        assert_eq!(parse_time("1:-2"), None);
+        assert_eq!(parse_time("nan"), None);
+        assert_eq!(parse_time("abc"), None);
+    }
+}
@@ -0,0 +1,136 @@
+/// Video codecs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec{
+    /// libx264 with the preset, tune and crf, without alpha
+    H264,
+    /// libx265 with the preset, tune and crf, without alpha
+    H265,
+    /// libvpx-vp9 with the crf, with alpha in .webm or .mkv
+    Vp9,
+    /// AV1 with libaom, slow but supports two pass
+    Av1Aom,
+    /// AV1 with SVT-AV1, much faster, 4:2:0 only
+    Av1Svt,
+    /// ProRes 422 HQ, in .mov
+    ProRes422,
+    /// ProRes 4444, with alpha, in .mov
+    ProRes4444,
+    /// FFV1, lossless, with alpha, in .mkv
+    Ffv1,
+    /// QuickTime Animation, lossless, with alpha, in .mov
+    Qtrle,
+    /// Animated GIF, with a palette generated from the whole video
+    Gif,
+}
+
+/// Pixel formats of the encoded video, the 10 variants have 10 bits per channel.
+/// With alpha the matching format with an alpha plane is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat{
+    Yuv420p, Yuv422p, Yuv444p, Yuv420p10, Yuv422p10, Yuv444p10,
+    /// RGB without chroma subsampling, for the lossless codecs
+    Rgb,
+}
+
+use PixelFormat::*;
+
+const YUV: &[PixelFormat] = &[Yuv420p, Yuv422p, Yuv444p, Yuv420p10, Yuv422p10, Yuv444p10];
+
+impl Codec{
+    pub(crate) fn encoder(&self) -> &'static str{
+        match self{
+            Self::H264 => "libx264",
+            Self::H265 => "libx265",
+            Self::Vp9 => "libvpx-vp9",
+            Self::Av1Aom => "libaom-av1",
+            Self::Av1Svt => "libsvtav1",
+            Self::ProRes422 | Self::ProRes4444 => "prores_ks",
+            Self::Ffv1 => "ffv1",
+            Self::Qtrle => "qtrle",
+            Self::Gif => "gif",
+        }
+    }
+
+    /// Pixel formats the encoder takes, the first is the default. GIF has a palette instead.
+    pub(crate) fn pixel_formats(&self, alpha: bool) -> &'static [PixelFormat]{
+        match (self, alpha){
+            (Self::H264 | Self::H265 | Self::Vp9 | Self::Av1Aom, false) => YUV,
+            (Self::Vp9, true) => &[Yuv420p],
+            (Self::Av1Svt, false) => &[Yuv420p, Yuv420p10],
+            (Self::ProRes422, false) => &[Yuv422p10],
+            (Self::ProRes4444, _) => &[Yuv444p10],
+            (Self::Ffv1, _) => &[Rgb, Yuv420p, Yuv422p, Yuv444p, Yuv420p10, Yuv422p10, Yuv444p10],
+            (Self::Qtrle, _) => &[Rgb],
+            _ => &[],
+        }
+    }
+
+    /// Name of a pixel format for ffmpeg
+    pub(crate) fn pix_fmt(&self, format: PixelFormat, alpha: bool) -> &'static str{
+        match (format, alpha){
+            (Yuv420p, false) => "yuv420p",
+            (Yuv420p, true) => "yuva420p",
+            (Yuv422p, false) => "yuv422p",
+            (Yuv422p, true) => "yuva422p",
+            (Yuv444p, false) => "yuv444p",
+            (Yuv444p, true) => "yuva444p",
+            (Yuv420p10, false) => "yuv420p10le",
+            (Yuv420p10, true) => "yuva420p10le",
+            (Yuv422p10, false) => "yuv422p10le",
+            (Yuv422p10, true) => "yuva422p10le",
+            (Yuv444p10, false) => "yuv444p10le",
+            (Yuv444p10, true) => "yuva444p10le",
+            (Rgb, false) if *self == Self::Qtrle => "rgb24",
+            (Rgb, true) if *self == Self::Qtrle => "argb",
+            (Rgb, false) => "bgr0",
+            (Rgb, true) => "bgra",
+        }
+    }
+
+    /// Extensions of containers the codec is known to fail in or lose the alpha in,
+    /// any other extension is left to ffmpeg
+    pub(crate) fn bad_containers(&self, alpha: bool) -> &'static [&'static str]{
+        match (self, alpha){
+            (Self::H264 | Self::H265, _) => &["webm", "gif"],
+            (Self::Vp9, false) | (Self::Av1Aom | Self::Av1Svt, _) => &["gif"],
+            (Self::Vp9, true) => &["mp4", "m4v", "mov", "gif"],
+            (Self::ProRes422 | Self::ProRes4444 | Self::Qtrle, _) => &["mp4", "m4v", "mkv", "webm", "gif"],
+            (Self::Ffv1, _) => &["mp4", "m4v", "webm", "gif"],
+            (Self::Gif, _) => &["mp4", "m4v", "mov", "mkv", "webm", "avi", "ts"],
+        }
+    }
+
+    /// Highest crf, None for codecs without rate control
+    pub(crate) fn max_crf(&self) -> Option<u32>{
+        match self{
+            Self::H264 | Self::H265 => Some(51),
+            Self::Vp9 | Self::Av1Aom | Self::Av1Svt => Some(63),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn has_two_pass(&self) -> bool{
+        matches!(self, Self::H264 | Self::H265 | Self::Vp9 | Self::Av1Aom)
+    }
+
+    /// Whether x264 style presets and tunes apply
+    pub(crate) fn is_x26x(&self) -> bool{
+        matches!(self, Self::H264 | Self::H265)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_formats() {
+        assert_eq!(Codec::Vp9.pixel_formats(true), &[Yuv420p]);
+        assert!(Codec::H264.pixel_formats(true).is_empty());
+        assert!(Codec::Gif.pixel_formats(false).is_empty());
+        assert_eq!(Codec::Qtrle.pix_fmt(Rgb, true), "argb");
+        assert_eq!(Codec::Ffv1.pix_fmt(Rgb, true), "bgra");
+        assert_eq!(Codec::ProRes4444.pix_fmt(Codec::ProRes4444.pixel_formats(true)[0], true), "yuva444p10le");
+        assert_eq!(Codec::Av1Svt.pix_fmt(Yuv420p10, false), "yuv420p10le");
+    }
+}
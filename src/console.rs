@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::mpsc::{ channel, Receiver, Sender };
+use std::thread::JoinHandle;
+
+/// Lines typed in the console, read on another thread to not block rendering.
+/// Dropping it stops the thread, so stdin is free again once live mode returns.
+pub(crate) struct Console{
+    lines: Receiver<String>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Console{
+    pub fn new() -> Self{
+        let (sender, lines) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::spawn(move || read(sender, &stop))
+        };
+        Self{ lines, stop, thread: Some(thread) }
+    }
+
+    /// The next line typed since the last call, if any
+    pub fn try_recv(&self) -> Option<String>{
+        self.lines.try_recv().ok()
+    }
+}
+
+impl Drop for Console{
+    fn drop(&mut self){
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take(){
+            // a blocking read can't be interrupted without poll, leave that thread behind
+            if cfg!(unix){
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+// how long to wait for input before checking whether to stop
+#[cfg(unix)]
+const POLL_MS: i32 = 50;
+
+// reads stdin directly instead of through std's buffered handle, so nothing typed is lost
+// in a buffer and stdin is only waited on with poll, never blocked on
+#[cfg(unix)]
+fn read(sender: Sender<String>, stop: &AtomicBool){
+    let mut pending = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !stop.load(Ordering::SeqCst){
+        let mut fd = libc::pollfd{ fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        let ready = unsafe{ libc::poll(&mut fd, 1, POLL_MS) };
+        if ready < 0{
+            return;
+        }
+        if ready == 0{
+            continue;
+        }
+        let read = unsafe{ libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr() as *mut _, buffer.len()) };
+        // closed or failed
+        if read <= 0{
+            return;
+        }
+        pending.extend_from_slice(&buffer[..read as usize]);
+        for line in split_lines(&mut pending){
+            if sender.send(line).is_err(){
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn read(sender: Sender<String>, stop: &AtomicBool){
+    let mut line = String::new();
+    while !stop.load(Ordering::SeqCst){
+        line.clear();
+        match std::io::stdin().read_line(&mut line){
+            Ok(0) | Err(_) => return,
+            Ok(_) => if sender.send(line.trim_end_matches(['\r', '\n']).to_string()).is_err(){
+                return;
+            },
+        }
+    }
+}
+
+// takes the complete lines out of the bytes read so far
+#[cfg_attr(not(unix), allow(dead_code))]
+fn split_lines(pending: &mut Vec<u8>) -> Vec<String>{
+    let Some(end) = pending.iter().rposition(|&b| b == b'\n') else { return Vec::new() };
+    let complete = pending.drain(..=end).collect::<Vec<_>>();
+    String::from_utf8_lossy(&complete).lines().map(|line| line.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let mut pending = b"12.5\n1:02".to_vec();
+        assert_eq!(split_lines(&mut pending), vec!["12.5"]);
+        assert_eq!(pending, b"1:02");
+        pending.extend_from_slice(b".5\r\n\n3\n");
+        assert_eq!(split_lines(&mut pending), vec!["1:02.5", "", "3"]);
+        assert!(pending.is_empty());
+        // dropping stops the thread instead of leaving it on stdin
+        drop(Console::new());
+    }
+}
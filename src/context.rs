@@ -0,0 +1,291 @@
+use khronos_egl as egl;
+
+use crate::error::Error;
+
+/// Title of the window
+pub const TITLE: &str = ":3";
+
+/// The OpenGL context everything is rendered with.
+/// Either a visible SDL window or an offscreen EGL context that needs no display.
+pub enum Context{
+    Window{
+        sdl: sdl2::Sdl,
+        window: sdl2::video::Window,
+        _gl_context: sdl2::video::GLContext,
+    },
+    Headless{
+        egl: Box<egl::DynamicInstance<egl::EGL1_4>>,
+        display: egl::Display,
+        context: egl::Context,
+        surface: Option<egl::Surface>,
+        screen_fbo: gl::types::GLuint,
+        screen_rbo: gl::types::GLuint,
+    },
+}
+
+/// Version of the GL context, desktop GL with a core profile or GLES
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlVersion{
+    pub major: u8,
+    pub minor: u8,
+    pub es: bool,
+}
+
+impl GlVersion{
+    /// The matching GLSL version, like "450 core" or "300 es"
+    pub fn glsl(&self) -> String{
+        match (self.es, self.major, self.minor){
+            (true, 2, _) => "100".to_string(),
+            (true, major, minor) => format!("{}{}0 es", major, minor),
+            (false, 3, 2) => "150 core".to_string(),
+            (false, major, minor) => format!("{}{}0 core", major, minor),
+        }
+    }
+
+    /// The generated headers need GLSL 3.30 or 3.00 es, so at least GL 3.3 or GLES 3.0
+    pub fn validate(&self) -> Result<(), Error>{
+        if self.es && self.major < 3{
+            return Err(Error::Config(format!(
+                "GLES {}.{} is not supported, Frag needs at least GLES 3.0", self.major, self.minor
+            )));
+        }
+        if !self.es && (self.major, self.minor) < (3, 3){
+            return Err(Error::Config(format!(
+                "GL {}.{} is not supported, Frag needs at least GL 3.3", self.major, self.minor
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for GlVersion{
+    fn default() -> Self{
+        Self{ major: 4, minor: 5, es: false }
+    }
+}
+
+// EGL_MESA_platform_surfaceless, lets Mesa (llvmpipe) render without any display server
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+impl Context{
+    /// Create a context, offscreen if headless
+    pub fn new(ww: i32, wh: i32, headless: bool, gl: GlVersion) -> Result<Self, Error>{
+        if headless { Self::headless(ww, wh, gl) }
+        else { Self::window(ww, wh, gl) }
+    }
+
+    /// Opens a window and creates a context for it
+    pub fn window(ww: i32, wh: i32, gl: GlVersion) -> Result<Self, Error>{
+        gl.validate()?;
+        let sdl = sdl2::init()
+            .map_err(|e| Error::Context(format!("could not initialize SDL: {}", e)))?;
+        let video_subsystem = sdl.video()
+            .map_err(|e| Error::Context(format!("could not initialize SDL video: {}", e)))?;
+
+        // has to be set before the context is created
+        let gl_attr = video_subsystem.gl_attr();
+        gl_attr.set_context_profile(
+            if gl.es { sdl2::video::GLProfile::GLES } else { sdl2::video::GLProfile::Core }
+        );
+        gl_attr.set_context_version(gl.major, gl.minor);
+
+        // window dimension must be the same or bigger as render dimensions, :/
+        let window = video_subsystem.window(TITLE, ww as u32, wh as u32)
+            .position_centered().opengl().build()
+            .map_err(|e| Error::Context(format!("could not create window: {}", e)))?;
+
+        //needs to exist
+        let _gl_context = window.gl_create_context()
+            .map_err(|e| Error::Context(format!("could not create GL context: {}", e)))?;
+
+        gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+
+        Ok(Self::Window{ sdl, window, _gl_context })
+    }
+
+    /// Creates an offscreen context, rendering into a framebuffer of the window size.
+    /// Prefers a surfaceless display and falls back to a pbuffer on the default display.
+    pub fn headless(ww: i32, wh: i32, gl: GlVersion) -> Result<Self, Error>{
+        gl.validate()?;
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }
+            .map_err(|e| Error::Context(format!("could not load EGL: {}", e)))?;
+
+        let surfaceless = egl.upcast::<egl::EGL1_5>().and_then(|egl15| unsafe {
+            egl15.get_platform_display(
+                PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE]
+            ).ok()
+        });
+        let display = match surfaceless{
+            Some(display) if egl.initialize(display).is_ok() => display,
+            _ => {
+                let display = unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }
+                    .ok_or_else(|| Error::Context("could not get EGL display.".to_string()))?;
+                egl.initialize(display)
+                    .map_err(|e| Error::Context(format!("could not initialize EGL: {}", e)))?;
+                display
+            },
+        };
+
+        let (api, renderable) = match (gl.es, gl.major){
+            (false, _) => (egl::OPENGL_API, egl::OPENGL_BIT),
+            (true, 2) => (egl::OPENGL_ES_API, egl::OPENGL_ES2_BIT),
+            (true, _) => (egl::OPENGL_ES_API, egl::OPENGL_ES3_BIT),
+        };
+        let config = egl.choose_first_config(display, &[
+            egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE, renderable,
+            egl::NONE,
+        ])
+            .map_err(|e| Error::Context(format!("could not choose EGL config: {}", e)))?
+            .ok_or_else(|| Error::Context("no suitable EGL config.".to_string()))?;
+        egl.bind_api(api)
+            .map_err(|e| Error::Context(format!("could not bind OpenGL API: {}", e)))?;
+        let mut attributes = vec![
+            egl::CONTEXT_MAJOR_VERSION, gl.major as egl::Int,
+            egl::CONTEXT_MINOR_VERSION, gl.minor as egl::Int,
+        ];
+        // at least 3.3, so there always is a core profile
+        if !gl.es{
+            attributes.extend([egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT]);
+        }
+        attributes.push(egl::NONE);
+        let context = egl.create_context(display, config, None, &attributes)
+            .map_err(|e| Error::Context(format!("could not create EGL context: {}", e)))?;
+
+        // nothing owns the context yet, so release it here if anything after fails.
+        // the display is shared by every context of the process and stays initialized
+        let mut surface = None;
+        let screen = make_current(&egl, display, config, context, &mut surface).and_then(|()| {
+            gl::load_with(|s| match egl.get_proc_address(s){
+                Some(f) => f as *const std::os::raw::c_void,
+                None => std::ptr::null(),
+            });
+            screen_framebuffer(ww, wh)
+        });
+        let (screen_fbo, screen_rbo) = match screen{
+            Ok(screen) => screen,
+            Err(e) => {
+                let _ = egl.make_current(display, None, None, None);
+                if let Some(surface) = surface{
+                    let _ = egl.destroy_surface(display, surface);
+                }
+                let _ = egl.destroy_context(display, context);
+                return Err(e);
+            },
+        };
+
+        Ok(Self::Headless{ egl: Box::new(egl), display, context, surface, screen_fbo, screen_rbo })
+    }
+
+    /// The framebuffer that ends up on screen, or gets read back when headless
+    pub fn screen_fbo(&self) -> gl::types::GLuint{
+        match self{
+            Self::Window{ .. } => 0,
+            Self::Headless{ screen_fbo, .. } => *screen_fbo,
+        }
+    }
+
+    /// Only a window has events, headless contexts return None
+    pub fn event_pump(&self) -> Result<Option<sdl2::EventPump>, Error>{
+        match self{
+            Self::Window{ sdl, .. } => sdl.event_pump().map(Some)
+                .map_err(|e| Error::Context(format!("could not get event pump: {}", e))),
+            Self::Headless{ .. } => Ok(None),
+        }
+    }
+
+    /// Set the title of the window, does nothing when headless
+    pub fn set_title(&mut self, title: &str){
+        if let Self::Window{ window, .. } = self{
+            // only fails on a nul byte
+            let _ = window.set_title(title);
+        }
+    }
+
+    /// Present the screen framebuffer, does nothing when headless
+    pub fn swap(&self){
+        if let Self::Window{ window, .. } = self{
+            window.gl_swap_window();
+        }
+    }
+}
+
+impl Drop for Context{
+    fn drop(&mut self){
+        if let Self::Headless{ egl, display, context, surface, screen_fbo, screen_rbo } = self{
+            unsafe{
+                gl::DeleteFramebuffers(1, screen_fbo);
+                gl::DeleteRenderbuffers(1, screen_rbo);
+            }
+            let _ = egl.make_current(*display, None, None, None);
+            if let Some(surface) = surface{
+                let _ = egl.destroy_surface(*display, *surface);
+            }
+            let _ = egl.destroy_context(*display, *context);
+        }
+    }
+}
+
+// try without any surface first, otherwise use a tiny pbuffer just to be current
+fn make_current(
+    egl: &egl::DynamicInstance<egl::EGL1_4>, display: egl::Display, config: egl::Config, context: egl::Context,
+    surface: &mut Option<egl::Surface>,
+) -> Result<(), Error>{
+    if egl.make_current(display, None, None, Some(context)).is_ok(){
+        return Ok(());
+    }
+    let pbuffer = egl.create_pbuffer_surface(display, config, &[
+        egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE,
+    ]).map_err(|e| Error::Context(format!("could not create EGL pbuffer: {}", e)))?;
+    *surface = Some(pbuffer);
+    egl.make_current(display, Some(pbuffer), Some(pbuffer), Some(context))
+        .map_err(|e| Error::Context(format!("could not make EGL context current: {}", e)))
+}
+
+// the "screen" is a framebuffer we can read back from
+fn screen_framebuffer(ww: i32, wh: i32) -> Result<(gl::types::GLuint, gl::types::GLuint), Error>{
+    let mut screen_fbo: gl::types::GLuint = 0;
+    let mut screen_rbo: gl::types::GLuint = 0;
+    unsafe{
+        gl::GenFramebuffers(1, &mut screen_fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, screen_fbo);
+        gl::GenRenderbuffers(1, &mut screen_rbo);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, screen_rbo);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, ww, wh);
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, screen_rbo
+        );
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE{
+            gl::DeleteFramebuffers(1, &screen_fbo);
+            gl::DeleteRenderbuffers(1, &screen_rbo);
+            return Err(Error::Gl("could not initialize headless framebuffer.".to_string()));
+        }
+    }
+    Ok((screen_fbo, screen_rbo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glsl_versions() {
+        let glsl = |major, minor, es| GlVersion{ major, minor, es }.glsl();
+        assert_eq!(glsl(4, 5, false), "450 core");
+        assert_eq!(glsl(3, 3, false), "330 core");
+        assert_eq!(glsl(3, 2, false), "150 core");
+        assert_eq!(glsl(3, 0, true), "300 es");
+        assert_eq!(glsl(3, 2, true), "320 es");
+        assert_eq!(glsl(2, 0, true), "100");
+    }
+
+    #[test]
+    fn old_versions_rejected() {
+        assert!(matches!(GlVersion{ major: 2, minor: 0, es: true }.validate(), Err(Error::Config(_))));
+        assert!(matches!(Context::headless(4, 4, GlVersion{ major: 2, minor: 0, es: true }), Err(Error::Config(_))));
+        assert!(GlVersion{ major: 3, minor: 0, es: true }.validate().is_ok());
+        assert!(matches!(GlVersion{ major: 2, minor: 1, es: false }.validate(), Err(Error::Config(_))));
+        assert!(matches!(GlVersion{ major: 3, minor: 2, es: false }.validate(), Err(Error::Config(_))));
+        assert!(GlVersion{ major: 3, minor: 3, es: false }.validate().is_ok());
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+
+/// How bad a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity{
+    Error, Warning
+}
+
+/// One error or warning from the GLSL compiler, mapped back to the streamed file it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic{
+    pub severity: Severity,
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        if let Some(file) = &self.file { write!(f, "{}:", file)?; }
+        if let Some(line) = self.line { write!(f, "{}:", line)?; }
+        if let Some(column) = self.column { write!(f, "{}:", column)?; }
+        let severity = match self.severity{
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.file.is_some() || self.line.is_some(){
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+/// Lines of source string n start at n * LINE_STRIDE + 1 on Mesa, which drops the source string number
+pub(crate) const LINE_STRIDE: usize = 100_000;
+
+// a diagnostic as the driver reports it, with the source string number instead of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Raw{
+    severity: Severity,
+    source: usize,
+    line: usize,
+    column: Option<usize>,
+    message: String,
+}
+
+/// Parses every error and warning in a shader info log and maps it to a file.
+/// Files are the names of the sources, indexed by the source string number of `#line`.
+/// Line numbers past `LINE_STRIDE`, only emitted for Mesa, are decoded into source string and line.
+/// Understands these formats:
+/// - Mesa: `0:16(5): error: message`
+/// - NVIDIA: `0(16) : error C1503: message`
+/// - AMD, Intel and glslang: `ERROR: 0:16: message`
+pub(crate) fn parse_log(log: &str, files: &[&str], failed: bool) -> Vec<Diagnostic>{
+    let diagnostics = log.lines()
+        .filter_map(parse_line)
+        .map(|mut raw| {
+            if raw.line > LINE_STRIDE{
+                raw.source = raw.line / LINE_STRIDE;
+                raw.line %= LINE_STRIDE;
+            }
+            raw
+        })
+        .map(|raw| Diagnostic{
+            severity: raw.severity,
+            file: files.get(raw.source).map(|file| file.to_string()),
+            line: Some(raw.line),
+            column: raw.column,
+            message: raw.message,
+        })
+        .collect::<Vec<_>>();
+    if diagnostics.is_empty() && failed{
+        // unknown format, better to show it than nothing
+        return vec![Diagnostic{
+            severity: Severity::Error, file: None, line: None, column: None,
+            message: log.trim().to_string(),
+        }];
+    }
+    diagnostics
+}
+
+fn parse_line(line: &str) -> Option<Raw>{
+    let line = line.trim();
+    // AMD, Intel and glslang put the severity first
+    for (prefix, severity) in [("ERROR:", Severity::Error), ("WARNING:", Severity::Warning)]{
+        if let Some(rest) = line.strip_prefix(prefix){
+            let (source, line, column, rest) = parse_location(rest.trim_start())?;
+            let message = rest.trim_start().strip_prefix(':')?.trim();
+            return Some(Raw{ severity, source, line, column, message: message.to_string() });
+        }
+    }
+    // Mesa and NVIDIA put the location first
+    let (source, line, column, rest) = parse_location(line)?;
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let (severity, rest) = if let Some(rest) = rest.strip_prefix("error"){
+        (Severity::Error, rest)
+    } else if let Some(rest) = rest.strip_prefix("warning"){
+        (Severity::Warning, rest)
+    } else {
+        return None;
+    };
+    let message = rest.trim_start().strip_prefix(':').unwrap_or(rest).trim();
+    Some(Raw{ severity, source, line, column, message: message.to_string() })
+}
+
+// "0:16(5)", "0:16" or "0(16)" into source string, line, column and the rest
+fn parse_location(s: &str) -> Option<(usize, usize, Option<usize>, &str)>{
+    let (source, rest) = parse_number(s)?;
+    if let Some(rest) = rest.strip_prefix(':'){
+        let (line, rest) = parse_number(rest)?;
+        if let Some(inner) = rest.strip_prefix('('){
+            let (column, after) = parse_number(inner)?;
+            let after = after.strip_prefix(')')?;
+            return Some((source, line, Some(column), after));
+        }
+        Some((source, line, None, rest))
+    } else {
+        let rest = rest.strip_prefix('(')?;
+        let (line, rest) = parse_number(rest)?;
+        let rest = rest.strip_prefix(')')?;
+        Some((source, line, None, rest))
+    }
+}
+
+fn parse_number(s: &str) -> Option<(usize, &str)>{
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let number = s[..end].parse().ok()?;
+    Some((number, &s[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILES: [&str; 3] = ["frag-internal", "lib.glsl", "shader.glsl"];
+
+    fn diag(severity: Severity, file: &str, line: usize, column: Option<usize>, message: &str)
+        -> Diagnostic
+    {
+        Diagnostic{
+            severity, file: Some(file.to_string()), line: Some(line), column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn mesa() {
+        let log = "2:4(5): error: `x' undeclared\n1:2(1): warning: unused\n";
+        assert_eq!(parse_log(log, &FILES, true), vec![
+            diag(Severity::Error, "shader.glsl", 4, Some(5), "`x' undeclared"),
+            diag(Severity::Warning, "lib.glsl", 2, Some(1), "unused"),
+        ]);
+    }
+
+    #[test]
+    fn nvidia() {
+        let log = "2(4) : error C1503: undefined variable \"x\"\n0(2) : warning C7022: unrecognized profile\n";
+        assert_eq!(parse_log(log, &FILES, true), vec![
+            diag(Severity::Error, "shader.glsl", 4, None, "C1503: undefined variable \"x\""),
+            diag(Severity::Warning, "frag-internal", 2, None, "C7022: unrecognized profile"),
+        ]);
+    }
+
+    #[test]
+    fn amd_intel() {
+        let log = "ERROR: 2:4: 'x' : undeclared identifier\nWARNING: 1:3: 'y' : unused\nERROR: 1 compilation errors.  No code generated.\n";
+        assert_eq!(parse_log(log, &FILES, true), vec![
+            diag(Severity::Error, "shader.glsl", 4, None, "'x' : undeclared identifier"),
+            diag(Severity::Warning, "lib.glsl", 3, None, "'y' : unused"),
+        ]);
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert!(parse_log("all good", &FILES, false).is_empty());
+        let diagnostics = parse_log("something broke\n", &FILES, true);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "something broke");
+        assert_eq!(diagnostics[0].to_string(), "error: something broke");
+    }
+
+    #[test]
+    fn strided_lines() {
+        let log = "0:200004(5): error: mesa\n2(200004) : error C0000: nvidia\n";
+        assert_eq!(parse_log(log, &FILES, true), vec![
+            diag(Severity::Error, "shader.glsl", 4, Some(5), "mesa"),
+            diag(Severity::Error, "shader.glsl", 4, None, "C0000: nvidia"),
+        ]);
+    }
+
+    #[test]
+    fn unknown_source() {
+        let diagnostics = parse_log("7:100(1): error: too far", &FILES, true);
+        assert_eq!(diagnostics[0].file, None);
+        assert_eq!(diagnostics[0].line, Some(100));
+        assert_eq!(diagnostics[0].to_string(), "100:1: error: too far");
+    }
+}
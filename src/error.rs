@@ -0,0 +1,87 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+
+/// Everything that can go wrong in Frag
+#[derive(Debug)]
+pub enum Error{
+    /// Could not create a window or GL context
+    Context(String),
+    /// Shader failed to compile, with every error and warning the compiler reported
+    Compile(Vec<Diagnostic>),
+    /// Program failed to link
+    Link(String),
+    /// Other GL failures, like incomplete framebuffers
+    Gl(String),
+    /// Reading or writing a file
+    Io{
+        path: String,
+        source: std::io::Error,
+    },
+    /// Decoding or encoding an image
+    Image{
+        path: String,
+        message: String,
+    },
+    /// Invalid content of a file Frag reads, like a params file or mouse track
+    Parse{
+        path: String,
+        message: String,
+    },
+    /// Could not watch a file for changes, path is empty if the watcher itself failed
+    Watch{
+        path: String,
+        source: notify::Error,
+    },
+    /// Spawning or talking to ffmpeg
+    FFmpeg(String),
+    /// Invalid configuration
+    Config(String),
+}
+
+impl Error{
+    pub(crate) fn io(path: &str, source: std::io::Error) -> Self{
+        Self::Io{ path: path.to_string(), source }
+    }
+
+    pub(crate) fn image(path: &str, message: impl fmt::Display) -> Self{
+        Self::Image{ path: path.to_string(), message: message.to_string() }
+    }
+
+    pub(crate) fn parse(path: &str, message: impl fmt::Display) -> Self{
+        Self::Parse{ path: path.to_string(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for Error{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            Self::Context(e) => write!(f, "Frag: {}", e),
+            Self::Compile(diagnostics) => {
+                write!(f, "Frag: could not compile shader:")?;
+                for diagnostic in diagnostics{
+                    write!(f, "\n\t{}", diagnostic)?;
+                }
+                Ok(())
+            },
+            Self::Link(e) => write!(f, "Frag: could not link program: {}", e),
+            Self::Gl(e) => write!(f, "Frag: {}", e),
+            Self::Io{ path, source } => write!(f, "Frag: {}: {}", path, source),
+            Self::Image{ path, message } => write!(f, "Frag: image {}: {}", path, message),
+            Self::Parse{ path, message } => write!(f, "Frag: could not parse {}: {}", path, message),
+            Self::Watch{ path, source } => write!(f, "Frag: could not watch {}: {}", path, source),
+            Self::FFmpeg(e) => write!(f, "Frag: ffmpeg: {}", e),
+            Self::Config(e) => write!(f, "Frag: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        match self{
+            Self::Io{ source, .. } => Some(source),
+            Self::Watch{ source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
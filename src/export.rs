@@ -0,0 +1,334 @@
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::error::Error;
+
+/// Format of the frames of an image sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat{
+    /// PNG with 8 bits per channel
+    Png8,
+    /// PNG with 16 bits per channel
+    Png16,
+    /// OpenEXR with 32 bit floats per channel
+    #[cfg(feature = "exr")]
+    Exr,
+}
+
+impl ImageFormat{
+    pub(crate) fn is_float(&self) -> bool{
+        *self != Self::Png8
+    }
+}
+
+/// Config for saving single frames, from live mode or with `FragConf::render_still`.
+/// Stills can be much bigger than the canvas, they are rendered in tiles of the canvas size
+/// and look like the window would at that size, pixelated if the canvas is.
+#[derive(Debug, Clone)]
+pub struct StillConf{
+    width: u32,
+    height: u32,
+    supersample: u32,
+    output: String,
+    format: ImageFormat,
+}
+
+impl StillConf{
+    /// Create default StillConf, window sized PNGs named after iTime
+    pub fn new() -> Self{
+        Self{
+            width: 0,
+            height: 0,
+            supersample: 1,
+            output: String::from("frag_{time}.png"),
+            format: ImageFormat::Png8,
+        }
+    }
+
+    /// Size of the image, 0 is the window size
+    pub fn with_size(mut self, width: u32, height: u32) -> Self{
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Render this many times bigger in both directions and average down, against aliasing
+    pub fn with_supersample(mut self, factor: u32) -> Self{
+        self.supersample = factor.max(1);
+        self
+    }
+
+    /// File name, `{time}` in the name of the file is replaced by iTime in seconds.
+    /// When the file exists, a number is added instead of overwriting it.
+    pub fn with_output(mut self, output: &str) -> Self{
+        self.output = output.to_string();
+        self
+    }
+
+    pub fn with_format(mut self, format: ImageFormat) -> Self{
+        self.format = format;
+        self
+    }
+
+    pub(crate) fn size(&self, (ww, wh): (i32, i32)) -> (i32, i32){
+        match (self.width, self.height){
+            (0, _) | (_, 0) => (ww, wh),
+            (w, h) => (w as i32, h as i32),
+        }
+    }
+
+    pub(crate) fn supersample(&self) -> u32{
+        self.supersample
+    }
+
+    pub(crate) fn format(&self) -> ImageFormat{
+        self.format
+    }
+
+    /// Write a still taken at a time, returns the path it was written to
+    pub(crate) fn write(&self, time: f32, w: i32, h: i32, pixels: &Pixels, alpha: bool) -> Result<String, Error>{
+        let (dir, name) = split_name(&self.output);
+        let path = unique_path(&format!("{}{}", dir, name.replace("{time}", &format!("{:.3}", time))));
+        create_parent(&path)?;
+        write_image(&path, w as u32, h as u32, pixels, self.format, alpha)?;
+        Ok(path)
+    }
+}
+
+impl Default for StillConf{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+/// Pixels read back from GL, RGBA with the bottom row first
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Pixels{
+    U8(Vec<u8>),
+    F32(Vec<f32>),
+}
+
+impl Pixels{
+    pub fn to_f32(&self) -> Vec<f32>{
+        match self{
+            Self::U8(data) => data.iter().map(|v| *v as f32 / 255.0).collect(),
+            Self::F32(data) => data.clone(),
+        }
+    }
+}
+
+/// Read the bound framebuffer. Floats keep the precision of float render targets,
+/// where reading floats is not supported, like GLES with an 8 bit target, it reads bytes instead.
+pub(crate) fn read_pixels(w: i32, h: i32, float: bool) -> Pixels{
+    let size = (w * h) as usize * 4;
+    unsafe{
+        if float{
+            let mut data: Vec<f32> = vec![0.0; size];
+            while gl::GetError() != gl::NO_ERROR {}
+            gl::ReadPixels(0, 0, w, h, gl::RGBA, gl::FLOAT, data.as_mut_ptr() as *mut c_void);
+            if gl::GetError() == gl::NO_ERROR{
+                return Pixels::F32(data);
+            }
+        }
+        let mut data: Vec<u8> = vec![0; size];
+        gl::ReadPixels(0, 0, w, h, gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as *mut c_void);
+        Pixels::U8(data)
+    }
+}
+
+/// Write pixels to a file, flipped to have the top row first like image files do.
+/// Without alpha, the alpha channel is dropped.
+pub(crate) fn write_image(path: &str, w: u32, h: u32, pixels: &Pixels, format: ImageFormat, alpha: bool)
+    -> Result<(), Error>
+{
+    match format{
+        ImageFormat::Png8 => {
+            let data = match pixels{
+                Pixels::U8(data) => data.clone(),
+                Pixels::F32(data) => data.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect(),
+            };
+            write_png(path, w, h, &channels(&flip(&data, w, h), alpha), png::BitDepth::Eight, alpha)
+        },
+        ImageFormat::Png16 => {
+            let data = flip(&pixels.to_f32(), w, h);
+            let bytes = channels(&data, alpha).iter()
+                .flat_map(|v| ((v.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes())
+                .collect::<Vec<_>>();
+            write_png(path, w, h, &bytes, png::BitDepth::Sixteen, alpha)
+        },
+        #[cfg(feature = "exr")]
+        ImageFormat::Exr => {
+            let data = flip(&pixels.to_f32(), w, h);
+            let (w, h) = (w as usize, h as usize);
+            if alpha{
+                exr::prelude::write_rgba_file(path, w, h, |x, y| {
+                    let i = (y * w + x) * 4;
+                    (data[i], data[i + 1], data[i + 2], data[i + 3])
+                })
+            } else {
+                exr::prelude::write_rgb_file(path, w, h, |x, y| {
+                    let i = (y * w + x) * 4;
+                    (data[i], data[i + 1], data[i + 2])
+                })
+            }.map_err(|e| Error::image(path, e))
+        },
+    }
+}
+
+fn write_png(path: &str, w: u32, h: u32, data: &[u8], depth: png::BitDepth, alpha: bool) -> Result<(), Error>{
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), w, h);
+    encoder.set_color(if alpha { png::ColorType::Rgba } else { png::ColorType::Rgb });
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header().map_err(|e| Error::image(path, e))?;
+    writer.write_image_data(data).map_err(|e| Error::image(path, e))
+}
+
+// reverse the order of the rows of RGBA pixels
+fn flip<T: Copy>(data: &[T], w: u32, h: u32) -> Vec<T>{
+    let row = w as usize * 4;
+    (0..h as usize).rev().flat_map(|y| data[y * row..(y + 1) * row].iter().copied()).collect()
+}
+
+// RGBA as is, or without alpha
+fn channels<T: Copy>(data: &[T], alpha: bool) -> Vec<T>{
+    if alpha{
+        return data.to_vec();
+    }
+    data.chunks(4).flat_map(|px| px[..3].iter().copied()).collect()
+}
+
+/// File name of a frame: the last run of `#` in the file name is replaced by the frame number,
+/// padded with zeros to its length. Without `#` the number is put in front of the extension.
+/// Directories are taken as they are.
+pub(crate) fn frame_path(pattern: &str, frame: usize) -> String{
+    let (dir, name) = split_name(pattern);
+    match name.rfind('#'){
+        Some(end) => {
+            let start = name[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+            format!("{}{}{:0width$}{}", dir, &name[..start], frame, &name[end + 1..], width = width)
+        },
+        None => numbered(pattern, frame),
+    }
+}
+
+// a path that does not exist yet, numbered in front of the extension if needed
+fn unique_path(path: &str) -> String{
+    (0..).map(|i| if i == 0 { path.to_string() } else { numbered(path, i) })
+        .find(|path| !Path::new(path).exists())
+        .expect("some number is free")
+}
+
+// the number after the file name, in front of its extension
+fn numbered(path: &str, n: usize) -> String{
+    let (dir, name) = split_name(path);
+    match Path::new(name).extension(){
+        Some(extension) => {
+            let stem = &name[..name.len() - extension.len() - 1];
+            format!("{}{}_{}.{}", dir, stem, n, extension.to_string_lossy())
+        },
+        None => format!("{}_{}", path, n),
+    }
+}
+
+// the directory up to and with its last separator, and the file name
+fn split_name(path: &str) -> (&str, &str){
+    let start = path.rfind(std::path::is_separator).map_or(0, |i| i + 1);
+    path.split_at(start)
+}
+
+/// Create the directory a file goes in
+pub(crate) fn create_parent(path: &str) -> Result<(), Error>{
+    match Path::new(path).parent(){
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            std::fs::create_dir_all(dir).map_err(|e| Error::io(&dir.to_string_lossy(), e))
+        },
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_paths() {
+        assert_eq!(frame_path("frames/frame_#####.png", 42), "frames/frame_00042.png");
+        assert_eq!(frame_path("a#b_##.exr", 123), "a#b_123.exr");
+        assert_eq!(frame_path("frame.png", 7), "frame_7.png");
+        assert_eq!(frame_path("frame", 7), "frame_7");
+        // only the file name has placeholders
+        assert_eq!(frame_path("take#2/frame.png", 7), "take#2/frame_7.png");
+        assert_eq!(frame_path("take#2/frame_###.png", 7), "take#2/frame_007.png");
+        assert_eq!(frame_path("v1.2/frame", 7), "v1.2/frame_7");
+    }
+
+    #[test]
+    fn still_paths() {
+        let dir = crate::test_dir("still_paths");
+        let conf = StillConf::new().with_output(&dir.join("still_{time}.png").to_string_lossy());
+        let pixels = Pixels::U8(vec![255; 4]);
+        let first = conf.write(1.25, 1, 1, &pixels, false).unwrap();
+        let second = conf.write(1.25, 1, 1, &pixels, false).unwrap();
+        assert!(first.ends_with("still_1.250.png"));
+        assert!(second.ends_with("still_1.250_1.png"));
+        assert!(conf.write(1.25, 1, 1, &pixels, false).unwrap().ends_with("still_1.250_2.png"));
+        // a literal # or {time} in a directory, and # in the file name, are kept as they are
+        let literal = dir.join("take#2 {time}").join("still#_{time}.png");
+        let literal = StillConf::new().with_output(&literal.to_string_lossy());
+        let first = literal.write(0.5, 1, 1, &pixels, false).unwrap();
+        let second = literal.write(0.5, 1, 1, &pixels, false).unwrap();
+        assert_eq!(Path::new(&first), dir.join("take#2 {time}").join("still#_0.500.png"));
+        assert_eq!(Path::new(&second), dir.join("take#2 {time}").join("still#_0.500_1.png"));
+        assert_eq!(conf.size((640, 360)), (640, 360));
+        assert_eq!(conf.with_size(7680, 4320).size((640, 360)), (7680, 4320));
+    }
+
+    #[test]
+    fn write_formats() {
+        // 1x2, bottom row red, top row half gray
+        let pixels = Pixels::F32(vec![1.0, 0.0, 0.0, 1.0, 0.5, 0.5, 0.5, 1.0]);
+        let dir = crate::test_dir("write_formats");
+        let path = dir.join("frame16.png").to_string_lossy().into_owned();
+        write_image(&path, 1, 2, &pixels, ImageFormat::Png16, false).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!((info.bit_depth, info.color_type), (png::BitDepth::Sixteen, png::ColorType::Rgb));
+        assert_eq!(&buffer[..6], &32768u16.to_be_bytes().repeat(3)[..]);
+        assert_eq!(&buffer[6..8], &[255, 255]);
+        let path = dir.join("frame8.png").to_string_lossy().into_owned();
+        write_image(&path, 1, 2, &Pixels::U8(vec![255, 0, 0, 255, 0, 0, 255, 255]), ImageFormat::Png8, false).unwrap();
+        let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        assert_eq!(buffer, vec![0, 0, 255, 255, 0, 0]);
+        // with alpha
+        let pixels = Pixels::U8(vec![255, 0, 0, 64, 0, 0, 255, 0]);
+        write_image(&path, 1, 2, &pixels, ImageFormat::Png8, true).unwrap();
+        let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(buffer, vec![0, 0, 255, 0, 255, 0, 0, 64]);
+    }
+
+    #[cfg(feature = "exr")]
+    #[test]
+    fn write_exr() {
+        let pixels = Pixels::F32(vec![2.5, 0.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.5]);
+        let path = crate::test_dir("write_exr").join("frame.exr").to_string_lossy().into_owned();
+        write_image(&path, 1, 2, &pixels, ImageFormat::Exr, true).unwrap();
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            &path,
+            |resolution, _| vec![(0.0f32, 0.0f32, 0.0f32, 0.0f32); resolution.width() * resolution.height()],
+            |pixels, position, (r, g, b, a): (f32, f32, f32, f32)| pixels[position.y()] = (r, g, b, a),
+        ).unwrap();
+        let pixels = image.layer_data.channel_data.pixels;
+        assert_eq!(pixels, vec![(0.0, 0.0, -1.0, 0.5), (2.5, 0.0, 0.0, 1.0)]);
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::{ Path, PathBuf };
+
+use crate::error::Error;
+use crate::diagnostic::*;
+
+/// A piece of a shader, starting at the given line of the file it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Source{
+    pub file: String,
+    pub content: String,
+    pub line: usize,
+}
+
+impl Source{
+    pub fn new(file: &str, content: &str, line: usize) -> Self{
+        Self{ file: file.to_string(), content: content.to_string(), line }
+    }
+}
+
+/// Resolves `#include "file"` and `#include <file>` in streamed files.
+/// Quoted includes are searched next to the including file first, then in the include paths.
+/// Every file is only included once per build.
+#[derive(Debug)]
+pub(crate) struct Includer<'a>{
+    include_paths: &'a [String],
+    // canonical paths of everything included so far
+    included: Vec<PathBuf>,
+    // files that were read, to be watched
+    pub files: Vec<String>,
+}
+
+impl<'a> Includer<'a>{
+    pub fn new(include_paths: &'a [String]) -> Self{
+        Self{ include_paths, included: Vec::new(), files: Vec::new() }
+    }
+
+    /// Split the content of a file into sources, with the included files in between
+    pub fn expand(&mut self, file: &str, content: &str) -> Result<Vec<Source>, Error>{
+        let mut sources = Vec::new();
+        let mut stack = Vec::new();
+        if let Ok(path) = Path::new(file).canonicalize(){
+            self.included.push(path.clone());
+            stack.push(path);
+        }
+        self.expand_into(file, content, &mut stack, &mut sources)?;
+        Ok(sources)
+    }
+
+    fn expand_into(&mut self, file: &str, content: &str, stack: &mut Vec<PathBuf>, sources: &mut Vec<Source>)
+        -> Result<(), Error>
+    {
+        let mut chunk = String::new();
+        let mut chunk_line = 1;
+        for (i, line) in content.split_inclusive('\n').enumerate(){
+            let target = match parse_include(line){
+                Some(target) => target,
+                None => {
+                    chunk.push_str(line);
+                    continue;
+                },
+            };
+            let line_nr = i + 1;
+            if !chunk.is_empty(){
+                sources.push(Source::new(file, &chunk, chunk_line));
+                chunk.clear();
+            }
+            chunk_line = line_nr + 1;
+            let fail = |message: String| Error::Compile(vec![Diagnostic{
+                severity: Severity::Error,
+                file: Some(file.to_string()),
+                line: Some(line_nr),
+                column: None,
+                message,
+            }]);
+            let resolved = self.resolve(file, target)
+                .ok_or_else(|| fail(format!("could not find include {}", line.trim())))?;
+            let path = Path::new(&resolved).canonicalize()
+                .map_err(|e| fail(format!("could not open include {}: {}", resolved, e)))?;
+            if stack.contains(&path){
+                let chain = stack.iter().chain(std::iter::once(&path))
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>();
+                return Err(fail(format!("include cycle: {}", chain.join(" -> "))));
+            }
+            if self.included.contains(&path){
+                continue;
+            }
+            let included = std::fs::read_to_string(&resolved)
+                .map_err(|e| fail(format!("could not read include {}: {}", resolved, e)))?;
+            self.included.push(path.clone());
+            self.files.push(resolved.clone());
+            stack.push(path);
+            self.expand_into(&resolved, &included, stack, sources)?;
+            stack.pop();
+        }
+        if !chunk.is_empty() || sources.is_empty(){
+            sources.push(Source::new(file, &chunk, chunk_line));
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, file: &str, target: Include) -> Option<String>{
+        let (name, local) = match target{
+            Include::Quoted(name) => (name, true),
+            Include::Angled(name) => (name, false),
+        };
+        let local_dir = Path::new(file).parent().map(|p| p.to_path_buf());
+        let dirs = local_dir.filter(|_| local).into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::from));
+        for dir in dirs{
+            let path = dir.join(name);
+            if path.is_file(){
+                return Some(path.to_string_lossy().into_owned());
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Include<'a>{
+    Quoted(&'a str),
+    Angled(&'a str),
+}
+
+fn parse_include(line: &str) -> Option<Include<'_>>{
+    let rest = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+    if let Some(rest) = rest.strip_prefix('"'){
+        return Some(Include::Quoted(&rest[..rest.find('"')?]));
+    }
+    if let Some(rest) = rest.strip_prefix('<'){
+        return Some(Include::Angled(&rest[..rest.find('>')?]));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf{
+        let dir = crate::test_dir(name);
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) -> String{
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn include_syntax() {
+        assert_eq!(parse_include("#include \"a.glsl\"\n"), Some(Include::Quoted("a.glsl")));
+        assert_eq!(parse_include("  # include <b.glsl> // comment"), Some(Include::Angled("b.glsl")));
+        assert_eq!(parse_include("#included"), None);
+        assert_eq!(parse_include("float x;"), None);
+    }
+
+    #[test]
+    fn include_once_and_paths() {
+        let dir = dir("include_once_and_paths");
+        let noise = write(&dir.join("lib"), "noise.glsl", "float noise;\n");
+        let util = write(&dir, "util.glsl", "#include <noise.glsl>\nfloat util;\n");
+        let main = write(&dir, "main.glsl", "float a;\n#include \"util.glsl\"\n#include <noise.glsl>\nfloat b;");
+        let paths = [dir.join("lib").to_string_lossy().into_owned()];
+        let mut includer = Includer::new(&paths);
+        let sources = includer.expand(&main, &std::fs::read_to_string(&main).unwrap()).unwrap();
+        assert_eq!(sources, vec![
+            Source::new(&main, "float a;\n", 1),
+            Source::new(&noise, "float noise;\n", 1),
+            Source::new(&util, "float util;\n", 2),
+            Source::new(&main, "float b;", 4),
+        ]);
+        assert_eq!(includer.files, vec![util, noise]);
+    }
+
+    #[test]
+    fn include_errors() {
+        let dir = dir("include_errors");
+        let a = write(&dir, "a.glsl", "\n#include \"b.glsl\"\n");
+        write(&dir, "b.glsl", "#include \"a.glsl\"\n");
+        let missing = write(&dir, "missing.glsl", "#include \"nope.glsl\"\n");
+        let error = Includer::new(&[]).expand(&a, &std::fs::read_to_string(&a).unwrap());
+        match error{
+            Err(Error::Compile(d)) => {
+                assert!(d[0].message.starts_with("include cycle"));
+                assert_eq!(d[0].line, Some(1));
+            },
+            _ => panic!("expected include cycle"),
+        }
+        match Includer::new(&[]).expand(&missing, "#include \"nope.glsl\"\n"){
+            Err(Error::Compile(d)) => assert_eq!((d[0].file.as_deref(), d[0].line), (Some(missing.as_str()), Some(1))),
+            _ => panic!("expected missing include"),
+        }
+    }
+}
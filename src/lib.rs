@@ -1,6 +1,6 @@
 /*!
 Example live coding, with pixel art like style:
-```
+```no_run
 use frag::*;
 let streamer = shader::ShaderStreamer::new()
     .with_file("lib.glsl")
@@ -15,7 +15,7 @@ FragConf::new()
     .run_live().expect("Could not run.");
 ```
 Example rendering to video:
-```
+```no_run
 use frag::*;
 let streamer = shader::ShaderStreamer::new()
     .with_file("lib.glsl")
@@ -33,12 +33,28 @@ FragConf::new()
     .with_output("render.mp4")
     .render().expect("Could not render.");
 ```
+Rendering without a window, for example on a server, using an offscreen EGL context:
+```no_run
+use frag::*;
+let streamer = shader::ShaderStreamer::new()
+    .with_file("shader.glsl");
+FragConf::new()
+    .with_window_width(1920)
+    .with_window_height(1080)
+    .with_headless(true)
+    .with_streamer(streamer)
+    .into_ffmpeg_renderer()
+    .with_output("render.mp4")
+    .render().expect("Could not render.");
+```
 !*/
 
 use sdl2::{
     event::Event,
     keyboard::Keycode,
     keyboard::Scancode,
+    keyboard::Mod,
+    mouse::MouseButton,
 };
 
 use std::{
@@ -46,15 +62,37 @@ use std::{
     convert::TryInto,
     io::prelude::*,
     process::{ Command, Stdio },
-    ffi::c_void,
     path::Path,
-    fs::File,
-    io::BufWriter,
     time::{ SystemTime, UNIX_EPOCH },
 };
 
 pub mod shader;
+pub mod texture;
+pub mod params;
+mod error;
+mod diagnostic;
+mod include;
+mod watch;
+mod context;
+mod pipeline;
+mod mouse;
+mod clock;
+mod audio;
+mod export;
+mod codec;
+mod console;
 use crate::shader::*;
+use crate::texture::*;
+use crate::context::*;
+use crate::pipeline::*;
+use crate::mouse::*;
+use crate::clock::{ Clock, parse_time, time_of_frame };
+use crate::export::*;
+use crate::console::Console;
+pub use crate::error::Error;
+pub use crate::diagnostic::{ Diagnostic, Severity };
+pub use crate::export::{ ImageFormat, StillConf };
+pub use crate::codec::{ Codec, PixelFormat };
 
 /// General config rendering
 #[derive(Debug)]
@@ -64,7 +102,27 @@ pub struct FragConf{
     ww: i32,
     wh: i32,
     pixelate: bool,
+    headless: bool,
+    gl: GlVersion,
+    glsl_version: Option<String>,
     streamer: Option<ShaderStreamer>,
+    buffers: Vec<(String, ShaderStreamer)>,
+    post_streamer: Option<ShaderStreamer>,
+    textures: Vec<(String, TextureConf)>,
+    mouse_recording: Option<String>,
+    uniforms: Vec<(String, UniformSource)>,
+    params_file: Option<String>,
+    fixed_framerate: Option<u32>,
+    throttle: bool,
+    loop_region: Option<(f32, f32)>,
+    timeline: bool,
+    stdin_control: bool,
+    audio: Option<String>,
+    still: StillConf,
+    canvas_format: CanvasFormat,
+    tonemap: Tonemap,
+    exposure: f32,
+    alpha: bool,
 }
 
 /// Config for rendering to file
@@ -73,11 +131,33 @@ pub struct FFmpegConf{
     base: FragConf,
     framerate: u32,
     preset: String,
-    tune: String,
+    tune: Option<String>,
     crf: u32,
     length: usize,
     start: usize,
     output: String,
+    mouse_track: Option<String>,
+    audio: Option<String>,
+    audio_codec: String,
+    audio_bitrate: u32,
+    codec: Codec,
+    pixel_format: Option<PixelFormat>,
+    bitrate: Option<u32>,
+    two_pass: bool,
+    extra_args: Vec<String>,
+}
+
+/// Config for rendering to numbered image files, without ffmpeg.
+/// Start, length and framerate work like they do for `FFmpegConf`.
+#[derive(Debug)]
+pub struct ImageSequenceConf{
+    base: FragConf,
+    framerate: u32,
+    length: usize,
+    start: usize,
+    output: String,
+    format: ImageFormat,
+    mouse_track: Option<String>,
 }
 
 /// FFMPEG presets
@@ -102,7 +182,27 @@ impl FragConf{
             ww: 0,
             wh: 0,
             pixelate: false,
+            headless: false,
+            gl: GlVersion::default(),
+            glsl_version: None,
             streamer: None,
+            buffers: Vec::new(),
+            post_streamer: None,
+            textures: Vec::new(),
+            mouse_recording: None,
+            uniforms: Vec::new(),
+            params_file: None,
+            fixed_framerate: None,
+            throttle: true,
+            loop_region: None,
+            timeline: true,
+            stdin_control: false,
+            audio: None,
+            still: StillConf::new(),
+            canvas_format: CanvasFormat::Rgba8,
+            tonemap: Tonemap::None,
+            exposure: 1.0,
+            alpha: false,
         }
     }
 
@@ -144,12 +244,209 @@ impl FragConf{
         self
     }
 
+    /// Format of the canvas and buffers, RGBA8 by default.
+    /// Float formats keep values outside [0, 1] for the post pass to tonemap, and precision for
+    /// feedback buffers and for exporting 16 bit PNG or EXR.
+    pub fn with_canvas_format(mut self, format: CanvasFormat) -> Self{
+        self.canvas_format = format;
+        self
+    }
+
+    /// Tonemapping the post pass applies to the canvas, none by default.
+    /// A post streamer can apply it with `tonemap(color)`.
+    pub fn with_tonemap(mut self, tonemap: Tonemap) -> Self{
+        self.tonemap = tonemap;
+        self
+    }
+
+    /// Multiplies the canvas before tonemapping, 1 by default
+    pub fn with_exposure(mut self, exposure: f32) -> Self{
+        self.exposure = exposure;
+        self
+    }
+
+    /// Keep the alpha of the canvas in exports, for overlaying in video editors:
+    /// RGBA PNG and EXR images, and video with a codec that has alpha, like `Codec::ProRes4444`.
+    /// The window does not show alpha.
+    pub fn with_alpha(mut self, alpha: bool) -> Self{
+        self.alpha = alpha;
+        self
+    }
+
+    /// Headless uses an offscreen context instead of a window, only for rendering to file.
+    /// Works without a display, for example with Mesa's llvmpipe.
+    pub fn with_headless(mut self, headless: bool) -> Self{
+        self.headless = headless;
+        self
+    }
+
+    /// Request a desktop GL context with a core profile, the default is 4.5.
+    /// The shaders need at least GLSL 3.30, so creating the context fails below GL 3.3.
+    pub fn with_gl_version(mut self, major: u8, minor: u8) -> Self{
+        self.gl = GlVersion{ major, minor, es: false };
+        self
+    }
+
+    /// Request a GLES context instead, at least 3.0, creating the context fails for older versions
+    pub fn with_gles_version(mut self, major: u8, minor: u8) -> Self{
+        self.gl = GlVersion{ major, minor, es: true };
+        self
+    }
+
+    /// Sets the #version of all shaders, like "330 core" or "300 es".
+    /// By default it matches the GL version.
+    pub fn with_glsl_version(mut self, version: &str) -> Self{
+        self.glsl_version = Some(version.to_string());
+        self
+    }
+
+    pub(crate) fn glsl_version(&self) -> String{
+        self.glsl_version.clone().unwrap_or_else(|| self.gl.glsl())
+    }
+
     /// Must provide a ShaderStreamer to render
     pub fn with_streamer(mut self, streamer: ShaderStreamer) -> Self{
         self.streamer = Some(streamer);
         self
     }
 
+    /// Adds a buffer pass, rendered before the main streamer every frame in the order they are added.
+    /// Its output can be sampled by every pass as `uniform sampler2D <name>` or `iChannelN`,
+    /// where N is the index of the buffer. A pass sampling itself or a later buffer gets the
+    /// output of the previous frame, which allows feedback effects.
+    pub fn with_buffer(mut self, name: &str, streamer: ShaderStreamer) -> Self{
+        self.buffers.push((name.to_string(), streamer));
+        self
+    }
+
+    /// Post process the canvas with your own shader, rendered at window resolution.
+    /// The canvas is available as `iCanvas`, `uv` goes from 0 to 1,
+    /// `iResolution` is the canvas resolution and `iWindowResolution` the window resolution.
+    pub fn with_post_streamer(mut self, streamer: ShaderStreamer) -> Self{
+        self.post_streamer = Some(streamer.into_post());
+        self
+    }
+
+    /// Adds a PNG image, available in every pass as `uniform sampler2D <name>`.
+    /// Reloaded when the file changes.
+    pub fn with_texture(self, name: &str, path: &str) -> Self{
+        self.with_texture_conf(name, TextureConf::new(path))
+    }
+
+    /// Adds a PNG image with custom filtering, wrapping and mipmaps
+    pub fn with_texture_conf(mut self, name: &str, conf: TextureConf) -> Self{
+        self.textures.push((name.to_string(), conf));
+        self
+    }
+
+    /// Adds a uniform with a constant value to every pass, declared automatically
+    pub fn with_uniform(self, name: &str, value: UniformValue) -> Self{
+        self.with_uniform_source(name, UniformSource::Const(value))
+    }
+
+    /// Adds a uniform whose value can be changed through the handle while running
+    pub fn with_uniform_handle(self, name: &str, handle: &UniformHandle) -> Self{
+        self.with_uniform_source(name, UniformSource::Handle(handle.clone()))
+    }
+
+    /// Adds a uniform computed from iTime every frame
+    pub fn with_uniform_fn(self, name: &str, f: impl Fn(f32) -> UniformValue + 'static) -> Self{
+        self.with_uniform_source(name, UniformSource::Fn(Box::new(f)))
+    }
+
+    /// Adds a custom uniform to every pass.
+    /// It is declared with the type of its value at the start, which must stay the same.
+    pub fn with_uniform_source(mut self, name: &str, source: UniformSource) -> Self{
+        self.uniforms.push((name.to_string(), source));
+        self
+    }
+
+    /// Analyze a WAV file and give every pass its sound at iTime, like Shadertoy's sound input:
+    /// - `uniform sampler2D iAudio`: 512x2, the spectrum at y 0.25 and the waveform at y 0.75
+    /// - `uniform float iBass, iMid, iTreble`: energy in the band, from 0 to 1 over the whole file
+    /// - `uniform float iBeat`: 1 on a beat in the bass, fading out after
+    ///
+    /// Only depends on iTime, so rendering to file is deterministic.
+    /// To hear it in the video too, use `FFmpegConf::with_audio`.
+    pub fn with_audio(mut self, path: &str) -> Self{
+        self.audio = Some(path.to_string());
+        self
+    }
+
+    /// Sidecar file for the values of params, tunable float uniforms annotated in the shader:
+    /// `uniform float speed; // @range(0, 10) @default(2) @key(Q/A)`.
+    /// Saved in live mode whenever a param is changed, loaded when running or rendering.
+    pub fn with_params_file(mut self, path: &str) -> Self{
+        self.params_file = Some(path.to_string());
+        self
+    }
+
+    /// In live mode, save iMouse over time to a file when closing.
+    /// Can be replayed when rendering with `FFmpegConf::with_mouse_track`.
+    pub fn with_mouse_recording(mut self, path: &str) -> Self{
+        self.mouse_recording = Some(path.to_string());
+        self
+    }
+
+    /// In live mode, advance time by exactly 1 / framerate every frame instead of following the clock,
+    /// like rendering to video does. With the framerate of the video, every frame gets the same
+    /// iTime, iDeltaTime and iFrame live as in the video.
+    pub fn with_fixed_timestep(mut self, framerate: u32) -> Self{
+        self.fixed_framerate = Some(framerate);
+        self
+    }
+
+    /// With a fixed timestep, wait so time runs no faster than real time, on by default.
+    /// Turn off to run as fast as possible, for example without vsync.
+    pub fn with_throttle(mut self, throttle: bool) -> Self{
+        self.throttle = throttle;
+        self
+    }
+
+    /// In live mode, loop time between start and end in seconds.
+    /// Can also be set while running with Home and End.
+    pub fn with_loop(mut self, start: f32, end: f32) -> Self{
+        self.loop_region = Some((start, end));
+        self
+    }
+
+    /// In live mode, show the timeline bar at the bottom of the window, on by default.
+    /// Can also be toggled with F1.
+    pub fn with_timeline(mut self, show: bool) -> Self{
+        self.timeline = show;
+        self
+    }
+
+    /// In live mode, jump to times typed in the console, off by default.
+    /// Stdin is read on another thread until live mode returns.
+    pub fn with_stdin_control(mut self, enabled: bool) -> Self{
+        self.stdin_control = enabled;
+        self
+    }
+
+    /// How stills are saved in live mode, Return saves what is on screen
+    /// and Shift+Return renders at the size of the StillConf.
+    pub fn with_still(mut self, still: StillConf) -> Self{
+        self.still = still;
+        self
+    }
+
+    /// Render a single frame at a time, with the size, supersampling and output of the StillConf.
+    /// Returns the path of the written image.
+    pub fn render_still(mut self, time: f32) -> Result<String, Error>{
+        let streamer = self.streamer.take()
+            .ok_or_else(|| Error::Config("no streamer to render.".to_string()))?;
+        // the pipeline is dropped before the context
+        let _context = Context::new(self.ww, self.wh, self.headless, self.gl)?;
+        let mut pipeline = Pipeline::new(&mut self, streamer)?;
+        let inputs = Inputs{
+            time,
+            date: Inputs::date(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()),
+            ..Default::default()
+        };
+        save_still(&mut pipeline, &inputs, &self.still, (self.ww, self.wh), self.alpha)
+    }
+
     /// Turn into a FFmpegConf, use to render to a video
     pub fn into_ffmpeg_renderer(self) -> FFmpegConf{
         FFmpegConf{
@@ -157,21 +454,46 @@ impl FragConf{
             framerate: 30,
             crf: 20,
             preset: String::from("medium"),
-            tune: String::from("film"),
+            tune: None,
             length: 60,
             start: 0,
             output: String::from("output.mp4"),
+            mouse_track: None,
+            audio: None,
+            audio_codec: String::from("aac"),
+            audio_bitrate: 192,
+            codec: Codec::H264,
+            pixel_format: None,
+            bitrate: None,
+            two_pass: false,
+            extra_args: Vec::new(),
+        }
+    }
+
+    /// Turn into an ImageSequenceConf, use to render to numbered image files
+    pub fn into_image_sequence_renderer(self) -> ImageSequenceConf{
+        ImageSequenceConf{
+            base: self,
+            framerate: 30,
+            length: 60,
+            start: 0,
+            output: String::from("frame_#####.png"),
+            format: ImageFormat::Png8,
+            mouse_track: None,
         }
     }
 
     /// Render continously, will update when files are changed
-    pub fn run_live(self) -> Result<(), String>{
-        let streamer = if let Some(streamer) = self.streamer { streamer }
+    pub fn run_live(mut self) -> Result<(), Error>{
+        if self.headless {
+            return Err(Error::Config("can not run live without a window.".to_string()));
+        }
+        let streamer = if let Some(streamer) = self.streamer.take() { streamer }
         else {
             println!("Frag: no streamer found, will use test streamer.");
             ShaderStreamer::test()
         };
-        run(self.cw, self.ch, self.ww, self.wh, self.pixelate, streamer)
+        run(self, streamer)
     }
 }
 
@@ -189,9 +511,11 @@ impl FFmpegConf{
         self
     }
 
-    /// Sets FFMPEG CRF, range [0, 52). Higher is more lossy. Recommended range 17-21.
+    /// Sets FFMPEG CRF, range [0, 52) for H.264 and H.265, [0, 64) for VP9 and AV1,
+    /// higher values are clamped to the maximum of the codec.
+    /// Higher is more lossy. Recommended range 17-21 for H.264.
     pub fn with_crf(mut self, crf: u32) -> Self{
-        self.crf = crf.min(51);
+        self.crf = crf.min(63);
         self
     }
 
@@ -211,16 +535,17 @@ impl FFmpegConf{
         self
     }
 
-    /// Sets FFMPEG tune
+    /// Sets FFMPEG tune, H.264 uses film by default.
+    /// H.265 only has Animation, Grain, FastDecode and ZeroLatency.
     pub fn with_tune(mut self, tune: Tune) -> Self{
-        self.tune = match tune{
+        self.tune = Some(match tune{
             Tune::Film => "film",
             Tune::Animation => "animation",
             Tune::Grain => "grain",
             Tune::StillImage => "stillimage",
             Tune::FastDecode => "fastdecode",
             Tune::ZeroLatency => "zerolatency",
-        }.to_string();
+        }.to_string());
         self
     }
 
@@ -242,433 +567,1408 @@ impl FFmpegConf{
         self
     }
 
+    /// Replay iMouse from a file recorded with `FragConf::with_mouse_recording`
+    pub fn with_mouse_track(mut self, path: &str) -> Self{
+        self.mouse_track = Some(path.to_string());
+        self
+    }
+
+    /// Mux an audio file, like WAV, OGG or MP3, into the video.
+    /// It is trimmed to the rendered frames: it starts at the time of the start frame and
+    /// is cut off at the end of the video.
+    pub fn with_audio(mut self, path: &str) -> Self{
+        self.audio = Some(path.to_string());
+        self
+    }
+
+    /// Sets the ffmpeg audio codec, like "aac", "libopus", "libmp3lame" or "copy", default "aac"
+    pub fn with_audio_codec(mut self, codec: &str) -> Self{
+        self.audio_codec = codec.to_string();
+        self
+    }
+
+    /// Sets the audio bitrate in kbit/s, default 192
+    pub fn with_audio_bitrate(mut self, kbps: u32) -> Self{
+        self.audio_bitrate = kbps;
+        self
+    }
+
+    /// Sets the video codec, H.264 by default.
+    /// Outputs with an extension the codec is known not to work in, like ProRes in .mp4, are refused.
+    pub fn with_codec(mut self, codec: Codec) -> Self{
+        self.codec = codec;
+        self
+    }
+
+    /// Sets the pixel format, by default 4:2:0 for lossy codecs and RGB for lossless ones.
+    /// With alpha the variant with an alpha plane is used.
+    pub fn with_pixel_format(mut self, format: PixelFormat) -> Self{
+        self.pixel_format = Some(format);
+        self
+    }
+
+    /// Sets a target bitrate in kbit/s instead of the crf
+    pub fn with_bitrate(mut self, kbps: u32) -> Self{
+        self.bitrate = Some(kbps);
+        self
+    }
+
+    /// Encode in two passes for a better bitrate distribution, needs a bitrate.
+    /// Frames are rendered twice. H.264, H.265, VP9 and libaom AV1 only.
+    pub fn with_two_pass(mut self, two_pass: bool) -> Self{
+        self.two_pass = two_pass;
+        self
+    }
+
+    /// Extra ffmpeg arguments, put right before the output, for anything not covered here
+    pub fn with_extra_args(mut self, args: &[&str]) -> Self{
+        self.extra_args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    // combinations ffmpeg would fail on, or silently drop the alpha of
+    fn validate(&self) -> Result<(), Error>{
+        let codec = self.codec;
+        let alpha = self.base.alpha;
+        let fail = |message: String| Err(Error::Config(message));
+        let extension = Path::new(&self.output).extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if self.length == 0{
+            return fail("the length is 0, there are no frames to render.".to_string());
+        }
+        if alpha && codec.pixel_formats(true).is_empty(){
+            return fail(format!("{:?} has no alpha, use ProRes4444, Vp9, Ffv1 or Qtrle.", codec));
+        }
+        // the extension only decides the container if the extra args don't
+        let forced = self.extra_args.iter().any(|arg| arg == "-f");
+        if !forced && codec.bad_containers(alpha).contains(&extension.as_str()){
+            let with = if alpha { " with alpha" } else { "" };
+            return fail(format!("{:?}{} can not go in a .{} file.", codec, with, extension));
+        }
+        if let Some(format) = self.pixel_format{
+            if !codec.pixel_formats(alpha).contains(&format){
+                let with = if alpha { " with alpha" } else { "" };
+                return fail(format!("{:?} can not encode {:?}{}.", codec, format, with));
+            }
+        }
+        if codec.max_crf().is_none() && self.bitrate.is_some(){
+            return fail(format!("{:?} has no bitrate.", codec));
+        }
+        if self.two_pass && !codec.has_two_pass(){
+            return fail(format!("{:?} can not encode in two passes.", codec));
+        }
+        if self.two_pass && self.bitrate.is_none(){
+            return fail("two pass encoding needs a bitrate.".to_string());
+        }
+        let x265_tunes = ["animation", "grain", "fastdecode", "zerolatency"];
+        if let (Codec::H265, Some(tune)) = (codec, self.tune.as_deref()){
+            if !x265_tunes.contains(&tune){
+                return fail(format!("H265 has no tune {}.", tune));
+            }
+        }
+        if self.audio.is_some(){
+            let opus = ["libopus", "libvorbis", "opus", "vorbis"].contains(&self.audio_codec.as_str());
+            if codec == Codec::Gif{
+                return fail("a GIF can not have audio.".to_string());
+            }
+            if extension == "webm" && !forced && !opus && self.audio_codec != "copy"{
+                return fail(format!("WebM needs libopus or libvorbis audio, not {}.", self.audio_codec));
+            }
+        }
+        Ok(())
+    }
+
+    // file ffmpeg writes the statistics of the first pass to
+    fn pass_log(&self) -> String{
+        format!("{}.2pass", self.output)
+    }
+
+    // arguments of the video codec, for the first or second of two passes
+    fn codec_args(&self, pass: Option<u32>) -> Vec<String>{
+        let codec = self.codec;
+        let alpha = self.base.alpha;
+        let mut args = vec!["-c:v".to_string(), codec.encoder().to_string()];
+        let mut push = |new: &[&str]| args.extend(new.iter().map(|arg| arg.to_string()));
+        if codec.is_x26x(){
+            push(&["-preset", &self.preset]);
+            match (codec, &self.tune){
+                (Codec::H264, tune) => push(&["-tune", tune.as_deref().unwrap_or("film")]),
+                (_, Some(tune)) => push(&["-tune", tune]),
+                _ => (),
+            }
+        }
+        match codec{
+            Codec::ProRes422 => push(&["-profile:v", "hq"]),
+            Codec::ProRes4444 => push(&["-profile:v", "4444"]),
+            Codec::Ffv1 => push(&["-level", "3"]),
+            _ => (),
+        }
+        if let Some(max) = codec.max_crf(){
+            let crf = format!("{}", self.crf.min(max));
+            match self.bitrate{
+                Some(kbps) => push(&["-b:v", &format!("{}k", kbps)]),
+                // VP9 and libaom need a zero bitrate for constant quality
+                None if matches!(codec, Codec::Vp9 | Codec::Av1Aom) => push(&["-crf", &crf, "-b:v", "0"]),
+                None => push(&["-crf", &crf]),
+            }
+        }
+        if let Some(format) = self.pixel_format.or_else(|| codec.pixel_formats(alpha).first().copied()){
+            push(&["-pix_fmt", codec.pix_fmt(format, alpha)]);
+        }
+        if let Some(pass) = pass{
+            let log = self.pass_log();
+            if codec == Codec::H265{
+                push(&["-x265-params", &format!("pass={}:stats={}.log", pass, log)]);
+            } else {
+                push(&["-pass", &format!("{}", pass), "-passlogfile", &log]);
+            }
+        }
+        args
+    }
+
+    // arguments for ffmpeg, reading raw frames from stdin.
+    // The first of two passes only writes statistics, without audio.
+    fn args(&self, pass: Option<u32>) -> Vec<String>{
+        let mut args: Vec<String> = [
+            "-r", &format!("{}", self.framerate),
+            "-f", "rawvideo", "-pix_fmt", "rgba",
+            "-s", &format!("{}x{}", self.base.ww, self.base.wh),
+            "-i", "-",
+        ].iter().map(|arg| arg.to_string()).collect();
+        let first_pass = pass == Some(1);
+        if let (Some(audio), false) = (&self.audio, first_pass){
+            let start = self.start as f64 / self.framerate as f64;
+            let length = self.length as f64 / self.framerate as f64;
+            args.extend([
+                "-ss", &format!("{}", start), "-i", audio,
+                "-map", "0:v:0", "-map", "1:a:0",
+                "-c:a", &self.audio_codec, "-b:a", &format!("{}k", self.audio_bitrate),
+                "-t", &format!("{}", length),
+            ].iter().map(|arg| arg.to_string()));
+        }
+        args.extend(["-threads", "0", "-y"].iter().map(|arg| arg.to_string()));
+        args.extend(self.codec_args(pass));
+        let filter = match self.codec{
+            // one palette for the whole video, from every frame
+            Codec::Gif => "vflip,split[a][b];[a]palettegen[p];[b][p]paletteuse",
+            _ => "vflip",
+        };
+        args.extend(["-vf", filter].iter().map(|arg| arg.to_string()));
+        args.extend(self.extra_args.iter().cloned());
+        if first_pass{
+            let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+            args.extend(["-an", "-f", "null", null].iter().map(|arg| arg.to_string()));
+        } else {
+            args.push(self.output.clone());
+        }
+        args
+    }
+
     /// Start rendering to video
-    pub fn render(mut self) -> Result<(), String>{
+    pub fn render(mut self) -> Result<(), Error>{
         let streamer = if let Some(streamer) = self.base.streamer {
             self.base.streamer = None;
             streamer
         }
         else {
-            return Err("Frag: no streamer found.".to_string());
+            return Err(Error::Config("no streamer found.".to_string()));
         };
         render(self, streamer)
     }
 }
 
-trait StringErr<U, V>{
-    fn strerr(self, msg: &str) -> Result<U, String>;
-    fn strerr_prop(self, f: &dyn Fn(V) -> String) -> Result<U, String>;
-}
+impl ImageSequenceConf{
+    /// Set framerate, the time between frames
+    pub fn with_framerate(mut self, fr: u32) -> Self{
+        self.framerate = fr;
+        self
+    }
 
-impl<U, V> StringErr<U, V> for Result<U, V>{
-    fn strerr(self, msg: &str) -> Result<U, String>{
-        match self{
-            Err(_) => Err(msg.to_string()),
-            Ok(x) => Ok(x),
-        }
+    /// Length in frames to be rendered
+    pub fn with_length(mut self, frames: usize) -> Self{
+        self.length = frames;
+        self
     }
 
-    fn strerr_prop(self, f: &dyn Fn(V) -> String) -> Result<U, String>{
-        match self{
-            Err(e) => Err(f(e)),
-            Ok(x) => Ok(x),
-        }
+    /// Start rendering from frame index
+    pub fn with_start(mut self, frame: usize) -> Self{
+        self.start = frame;
+        self
+    }
+
+    /// File names of the frames, the last run of `#` in the file name is replaced by the frame index
+    /// padded with zeros. Directories are created. Defaults to `frame_#####.png`.
+    pub fn with_output(mut self, pattern: &str) -> Self{
+        self.output = pattern.to_string();
+        self
+    }
+
+    /// Sets the format of the frames, the extension of the output is not changed
+    pub fn with_format(mut self, format: ImageFormat) -> Self{
+        self.format = format;
+        self
+    }
+
+    /// Replay iMouse from a file recorded with `FragConf::with_mouse_recording`
+    pub fn with_mouse_track(mut self, path: &str) -> Self{
+        self.mouse_track = Some(path.to_string());
+        self
+    }
+
+    /// Start rendering the frames
+    pub fn render(mut self) -> Result<(), Error>{
+        let streamer = self.base.streamer.take()
+            .ok_or_else(|| Error::Config("no streamer found.".to_string()))?;
+        render_sequence(self, streamer)
     }
 }
 
-fn render(conf: FFmpegConf, mut streamer: ShaderStreamer) -> Result<(), String> {
-    let (sdl_context, _window, _gl_contex)
-        = init_context(conf.base.ww, conf.base.wh).strerr("Frag: could not create context.")?;
+fn render(mut conf: FFmpegConf, streamer: ShaderStreamer) -> Result<(), Error> {
+    let timing = Timing{
+        framerate: conf.framerate, start: conf.start, length: conf.length, mouse_track: conf.mouse_track.clone()
+    };
+    conf.validate()?;
+    let alpha = conf.base.alpha;
+    let mut renderer = FrameRenderer::new(&mut conf.base, streamer, timing)?;
 
-    let (mut render_program, post_program) = init_programs(&mut streamer);
-    let (i_time, i_delta_time, i_frame, _, _)
-        = init_uniforms(&mut render_program, conf.base.cw, conf.base.ch);
-    let vao = init_quad();
-    let (canvas_fbo, canvas_tex)
-        = init_rendertarget(conf.base.cw, conf.base.ch, conf.base.pixelate)?;
+    if let Some(audio) = &conf.audio{
+        // a missing file is clearer before ffmpeg starts
+        std::fs::metadata(audio).map_err(|e| Error::io(audio, e))?;
+    }
 
-    let frame_time = 1.0 / conf.framerate as f32;
-    let (mut t, mut dt, mut frame, mut sec) = (frame_time * conf.start as f32, 0.0, 0usize, 0.0);
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    // the same frames are rendered again for the second pass
+    let passes = if conf.two_pass { vec![Some(1), Some(2)] } else { vec![None] };
+    for pass in passes{
+        if let Some(pass) = pass{
+            println!("Frag: pass {} of 2.", pass);
+            renderer.reset();
+        }
+        encode(&mut renderer, conf.args(pass), alpha)?;
+    }
+    if conf.two_pass{
+        remove_pass_logs(&conf.pass_log());
+    }
+    Ok(())
+}
 
+// render all frames into an ffmpeg process and wait for it to finish
+fn encode(renderer: &mut FrameRenderer, args: Vec<String>, alpha: bool) -> Result<(), Error>{
     // FFmpeg code adapted from:
     // http://blog.mmacklin.com/2013/06/11/real-time-video-capture-with-ffmpeg/
     let command = "ffmpeg";
-    let args = [
-        "-r", &format!("{}", conf.framerate),
-        "-f", "rawvideo", "-pix_fmt", "rgba",
-        "-s", &format!("{}x{}", conf.base.ww, conf.base.wh),
-        "-i", "-", "-threads", "0", "-preset", &conf.preset, "-tune", &conf.tune,
-        "-y", "-pix_fmt", "yuv420p", "-crf", &format!("{}", conf.crf),
-        "-vf", "vflip",
-        &conf.output
-    ];
-    let process = Command::new(command)
+    let mut process = Command::new(command)
         .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()
-        .strerr_prop(&|e| format!("Frag: couldn't spawn ffmpeg: {}", e))?;
+        .map_err(|e| Error::FFmpeg(format!("couldn't spawn ffmpeg: {}", e)))?;
 
-    let mut stdin = process.stdin.unwrap();
+    let mut stdin = process.stdin.take().ok_or_else(|| Error::FFmpeg("no stdin.".to_string()))?;
 
-    let start = Instant::now();
-    'running: loop {
-        let lt = t;
-        for event in event_pump.poll_iter() {
-            if let Event::Quit{ .. } = event{
-                break 'running;
-            }
-        }
-        unsafe{
-            // render to texture
-            gl::BindFramebuffer(gl::FRAMEBUFFER, canvas_fbo);
-            render_program.set_used();
-            gl::Viewport(0, 0, conf.base.cw, conf.base.ch);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-            gl::BindVertexArray(vao);
-            i_time.set_1f(t);
-            i_delta_time.set_1f(dt);
-            i_frame.set_1ui(frame.try_into().unwrap());
-            gl::DrawArrays(gl::TRIANGLES, 0, 6);
-            //render to screen, skip if there is no scaling
-            if !(conf.base.ww == conf.base.cw && conf.base.wh == conf.base.ch){
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-                post_program.set_used();
-                gl::Viewport(0, 0, conf.base.ww, conf.base.wh);
-                gl::BindTexture(gl::TEXTURE_2D, canvas_tex);
-                gl::DrawArrays(gl::TRIANGLES, 0, 6);
-            }
+    renderer.render(false, alpha, |_, pixels| {
+        let Pixels::U8(buffer) = pixels else { unreachable!("bytes were read") };
+        stdin
+            .write_all(&buffer)
+            .map_err(|why| Error::FFmpeg(format!("couldn't write to ffmpeg stdin: {}", why)))
+    })?;
+    std::mem::drop(stdin);
+
+    let mut s = String::new();
+    if let Some(mut stdout) = process.stdout.take(){
+        match stdout.read_to_string(&mut s) {
+            Err(why) => println!("couldn't read ffmpeg stdout: {}", why),
+            Ok(_) => println!("ffmpeg responded with:\n{}", s),
         }
+    }
+    let status = process.wait().map_err(|e| Error::FFmpeg(format!("couldn't wait for ffmpeg: {}", e)))?;
+    if !status.success(){
+        return Err(Error::FFmpeg(format!("ffmpeg failed with {}.", status)));
+    }
+    Ok(())
+}
 
-        let mut buffer: Vec<u8> = vec![0; (conf.base.ww * conf.base.wh) as usize * 4];
-        unsafe{
-            gl::ReadPixels(
-                0, 0, conf.base.ww, conf.base.wh,
-                gl::RGBA, gl::UNSIGNED_BYTE,
-                buffer.as_mut_ptr() as *mut c_void
-            );
+// the statistics of two pass encoding, ffmpeg adds a suffix to the name of the log
+fn remove_pass_logs(log: &str){
+    let path = Path::new(log);
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else { return };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let name = name.to_string_lossy();
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten(){
+        if entry.file_name().to_string_lossy().starts_with(name.as_ref()){
+            let _ = std::fs::remove_file(entry.path());
         }
+    }
+}
 
-        stdin
-            .write_all(&buffer)
-            .strerr_prop(&|why| format!("Frag: couldn't write to ffmpeg stdin: {}", why))?;
+fn render_sequence(mut conf: ImageSequenceConf, streamer: ShaderStreamer) -> Result<(), Error> {
+    if conf.length == 0{
+        return Err(Error::Config("the length is 0, there are no frames to render.".to_string()));
+    }
+    let timing = Timing{
+        framerate: conf.framerate, start: conf.start, length: conf.length, mouse_track: conf.mouse_track.clone()
+    };
+    let (ww, wh) = (conf.base.ww as u32, conf.base.wh as u32);
+    let alpha = conf.base.alpha;
+    let mut renderer = FrameRenderer::new(&mut conf.base, streamer, timing)?;
+    create_parent(&frame_path(&conf.output, 0))?;
+    renderer.render(conf.format.is_float(), alpha, |frame, pixels| {
+        write_image(&frame_path(&conf.output, frame), ww, wh, &pixels, conf.format, alpha)
+    })
+}
 
-        frame += 1;
-        if frame > conf.length { break; }
-        t += frame_time;
-        let rt = start.elapsed().as_millis() as f32 / 1000.0;
-        dt = t - lt;
-        if rt.floor() > sec{
-            sec = rt.floor();
-            println!("{} / {} frames", frame, conf.length);
-            std::io::stdout().flush().strerr("Frag: could not flush stdout.")?;
-        }
+/// Frames to render to file
+#[derive(Debug, Clone)]
+struct Timing{
+    framerate: u32,
+    start: usize,
+    length: usize,
+    mouse_track: Option<String>,
+}
+
+// renders frames to file, every frame exactly 1 / framerate apart
+struct FrameRenderer{
+    // dropped before the context
+    pipeline: Pipeline,
+    context: Context,
+    timing: Timing,
+    mouse_track: MouseTrack,
+    size: (i32, i32),
+}
+
+impl FrameRenderer{
+    fn new(base: &mut FragConf, streamer: ShaderStreamer, timing: Timing) -> Result<Self, Error>{
+        let context = Context::new(base.ww, base.wh, base.headless, base.gl)?;
+        let pipeline = Pipeline::new(base, streamer)?;
+        let mouse_track = match &timing.mouse_track{
+            Some(path) => MouseTrack::load(path)?,
+            None => MouseTrack::default(),
+        };
+        Ok(Self{ context, pipeline, timing, mouse_track, size: (base.ww, base.wh) })
     }
 
-    unsafe{
-        gl::DeleteFramebuffers(1, &canvas_fbo);
+    // start over from black buffers, so rendering again gives the same frames
+    fn reset(&mut self){
+        self.pipeline.reset();
     }
 
-    std::mem::drop(stdin);
+    // gives the number and pixels of every frame to write, stops early when the window is closed
+    fn render(&mut self, float: bool, alpha: bool, mut write: impl FnMut(usize, Pixels) -> Result<(), Error>)
+        -> Result<(), Error>
+    {
+        let timing = &self.timing;
+        if timing.length == 0{
+            return Ok(());
+        }
+        let time = |frame: usize| time_of_frame(frame as i64, timing.framerate);
+        let (mut t, mut dt, mut frame, mut sec) = (time(timing.start), 0.0, 0usize, 0.0);
+        let mut event_pump = self.context.event_pump()?;
+        let start = Instant::now();
+        let start_date = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        'running: loop {
+            let lt = t;
+            if let Some(event_pump) = &mut event_pump{
+                for event in event_pump.poll_iter() {
+                    if let Event::Quit{ .. } = event{
+                        break 'running;
+                    }
+                }
+            }
+            //render to screen, skip if there is no scaling or post processing.
+            //exports with alpha or of a float canvas go to a target in the canvas format instead,
+            //the screen has 8 bits and no alpha
+            let screen_fbo = if !self.pipeline.needs_post(){
+                None
+            } else if alpha || float && self.pipeline.canvas_format().is_float(){
+                Some(self.pipeline.output_fbo()?)
+            } else {
+                Some(self.context.screen_fbo())
+            };
+            // iFrame counts from time 0 like live, not from the start
+            let inputs = Inputs{
+                time: t,
+                delta_time: dt,
+                frame: (timing.start + frame).try_into().unwrap(),
+                frame_rate: timing.framerate as f32,
+                mouse: self.mouse_track.at(t),
+                date: Inputs::date(start_date + std::time::Duration::from_secs_f32(t.max(0.0))),
+            };
+            self.pipeline.draw(&inputs, screen_fbo);
+            write(timing.start + frame, read_pixels(self.size.0, self.size.1, float))?;
 
-    let mut s = String::new();
-    match process.stdout.unwrap().read_to_string(&mut s) {
-        Err(why) => println!("couldn't read ffmpeg stdout: {}", why),
-        Ok(_) => println!("ffmpeg responded with:\n{}", s),
+            frame += 1;
+            if frame >= timing.length { break; }
+            t = time(timing.start + frame);
+            let rt = start.elapsed().as_millis() as f32 / 1000.0;
+            dt = t - lt;
+            if rt.floor() > sec{
+                sec = rt.floor();
+                println!("{} / {} frames", frame, timing.length);
+                let _ = std::io::stdout().flush();
+            }
+        }
+        Ok(())
     }
-    Ok(())
 }
 
-fn run(
-    cw: i32, ch: i32, ww: i32, wh: i32, pixelate: bool, mut streamer: ShaderStreamer
-) -> Result<(), String>{
-    let (sdl_context, window, _gl_contex)
-        = init_context(ww, wh).strerr("Frag: could not create context.")?;
-    let (mut render_program, post_program) = init_programs(&mut streamer);
-    let (mut i_time, mut i_delta_time, mut i_frame, mut i_aspect, mut i_resolution)
-        = init_uniforms(&mut render_program, cw, ch);
-    let vao = init_quad();
-    let (canvas_fbo, canvas_tex) = init_rendertarget(cw, ch, pixelate)?;
-
-    let (mut t, mut dt, mut frame, mut sec, mut last_frames, mut play_t)
-        = (0.0, 0.0, 0, 0.0, 0, 0.0);
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    streamer.start();
+fn run(mut conf: FragConf, streamer: ShaderStreamer) -> Result<(), Error>{
+    let (ww, wh) = (conf.ww, conf.wh);
+    let size = (conf.cw, conf.ch, ww, wh);
+    let mut context = Context::window(ww, wh, conf.gl)?;
+    let mut pipeline = Pipeline::new(&mut conf, streamer)?;
+    let mut mouse = Mouse::default();
+    let mut mouse_track = MouseTrack::default();
+
+    let (mut dt, mut frame, mut drawn, mut sec, mut last_frames) = (0.0, 0, 0, 0.0, 0);
+    // time of the last frame the passes rendered, refreshes at the same time render it again
+    let mut frame_drawn_at: Option<f32> = None;
+    let mut event_pump = context.event_pump()?
+        .ok_or_else(|| Error::Context("no events without a window.".to_string()))?;
+    pipeline.start()?;
     let start = Instant::now();
+    let mut clock = Clock::new(conf.fixed_framerate, conf.throttle);
+    if let Some((start, end)) = conf.loop_region{
+        clock.set_loop(start, end);
+    }
+    let mut show_timeline = conf.timeline;
+    // whether to render a still at full size, or save what is on screen
+    let mut save_requested: Option<bool> = None;
+    // a time typed in the window after pressing /
+    let mut time_entry: Option<String> = None;
+    // times typed in the console, the reading thread stops when this is dropped
+    let console = conf.stdin_control.then(Console::new);
     let mut playing = true;
-    let mut lt;
+    let mut last_loop = Instant::now();
+    let mut params_dirty = false;
+    if !pipeline.params().specs.is_empty(){
+        pipeline.params().print();
+    }
     'running: loop {
-        lt = t;
         let mut need_refresh = false;
+        let mut params_changed = false;
+        while let Some(line) = console.as_ref().and_then(Console::try_recv){
+            if !line.trim().is_empty(){
+                jump(&mut clock, &line);
+                need_refresh = true;
+            }
+        }
         for event in event_pump.poll_iter() {
+            if let Some(mut entry) = time_entry.take(){
+                match event{
+                    Event::Quit{ .. } => break 'running,
+                    Event::TextInput{ text, .. } => {
+                        entry.extend(text.chars().filter(|c| c.is_ascii_digit() || ".:-".contains(*c)));
+                        time_entry = Some(entry);
+                    },
+                    Event::KeyDown{ keycode: Some(Keycode::Backspace), .. } => {
+                        entry.pop();
+                        time_entry = Some(entry);
+                    },
+                    Event::KeyDown{ keycode: Some(Keycode::Return | Keycode::KpEnter), .. } => {
+                        jump(&mut clock, &entry);
+                        need_refresh = true;
+                    },
+                    Event::KeyDown{ keycode: Some(Keycode::Escape), .. } => (),
+                    _ => time_entry = Some(entry),
+                }
+                match &time_entry{
+                    Some(entry) => context.set_title(&format!("go to: {}", entry)),
+                    None => context.set_title(TITLE),
+                }
+                continue;
+            }
             match event {
                 Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
                 Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
                     playing = !playing;
-                    lt = start.elapsed().as_millis() as f32 / 1000.0;
+                    clock.sync();
                 }
-                Event::KeyDown { keycode: Some(Keycode::Return), .. } => {
-                    let filename = match SystemTime::now().duration_since(UNIX_EPOCH){
-                        Ok(n) => format!("{}.png", n.as_secs()),
-                        Err(_) => "0.png".to_string(),
-                    };
-                    let path = Path::new(&filename);
-                    let file = File::create(path)
-                        .expect("Frag: could not open file for frame image.");
-                    let w = &mut BufWriter::new(file);
-
-                    let mut encoder = png::Encoder::new(w, ww as u32, wh as u32);
-                    encoder.set_color(png::ColorType::Rgb);
-                    encoder.set_depth(png::BitDepth::Eight);
-                    let mut writer = encoder.write_header()
-                        .expect("Frag: could not write png header for frame image.");
-
-                    let sw = ww as usize;
-                    let sh = wh as usize;
-                    let size = sw * sh;
-                    let mut buffer: Vec<u8> = vec![0; size * 3];
-                    unsafe{
-                        gl::ReadPixels(
-                            0, 0, ww, wh,
-                            gl::RGB, gl::UNSIGNED_BYTE,
-                            buffer.as_mut_ptr() as *mut c_void
-                        );
-                    }
-                    // flip y
-                    let mut transformed = vec![0; size * 3];
-                    for y in 0..sh{
-                    for x in 0..sw * 3{
-                        transformed[(sh - y - 1) * sw * 3 + x] = buffer[y * sw * 3 + x];
-                    }
-                    }
-
-                    if let Err(e) = writer.write_image_data(&transformed){
-                        println!("Frag: could not save frame image: {}", e);
-                    }
+                Event::KeyDown { keycode: Some(Keycode::Return), keymod, .. } => {
+                    save_requested = Some(keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD));
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::Slash), .. } => {
+                    time_entry = Some(String::new());
+                    context.set_title("go to: ");
+                },
+                Event::KeyDown { keycode: Some(Keycode::Comma), .. } => {
+                    playing = false;
+                    clock.step(-1);
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::Period), .. } => {
+                    playing = false;
+                    clock.step(1);
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } => {
+                    clock.slower();
+                    println!("Frag: speed {}x.", clock.speed());
+                },
+                Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } => {
+                    clock.faster();
+                    println!("Frag: speed {}x.", clock.speed());
+                },
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                    clock.reverse();
+                    println!("Frag: speed {}x.", clock.speed());
+                },
+                Event::KeyDown { keycode: Some(Keycode::Home), .. } => {
+                    clock.set_loop_in();
+                    print_loop(&clock);
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::End), .. } => {
+                    clock.set_loop_out();
+                    print_loop(&clock);
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::Delete), .. } => {
+                    clock.clear_loop();
+                    print_loop(&clock);
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                    show_timeline = !show_timeline;
+                    need_refresh = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
+                    pipeline.params().select_next();
+                    pipeline.params().print();
+                },
+                Event::MouseMotion { mousestate, xrel, .. } if mousestate.right() => {
+                    let params = pipeline.params();
+                    params.adjust(params.selected(), xrel as f32 / ww as f32);
+                    params_changed = true;
+                },
+                Event::MouseMotion { x, y, .. } => {
+                    let old = mouse.uniform();
+                    mouse.motion(Mouse::to_canvas(x, y, size));
+                    need_refresh = need_refresh || old != mouse.uniform();
+                },
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    mouse.press(Mouse::to_canvas(x, y, size));
+                    need_refresh = true;
+                },
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                    mouse.release();
+                    need_refresh = true;
                 },
                 Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
-                    play_t = 0.0;
-                    lt = start.elapsed().as_millis() as f32 / 1000.0;
+                    clock.seek(0.0);
                     need_refresh = true;
                 },
                 Event::KeyDown { keycode: Some(Keycode::PageUp), .. } => {
-                    play_t += 5.0;
-                    lt = start.elapsed().as_millis() as f32 / 1000.0;
+                    clock.shift(5.0);
                     need_refresh = true;
                 },
                 Event::KeyDown { keycode: Some(Keycode::PageDown), .. } => {
-                    play_t -= 5.0;
-                    lt = start.elapsed().as_millis() as f32 / 1000.0;
+                    clock.shift(-5.0);
                     need_refresh = true;
                 },
                 _ => {}
             }
         }
+        // tune params with their keys, a full range takes two seconds
+        let real_dt = last_loop.elapsed().as_secs_f32();
+        last_loop = Instant::now();
+        // keys being held are typed into the time entry instead
+        let typing = time_entry.is_some();
+        let keyboard = event_pump.keyboard_state();
+        let pressed = |name: &str| Scancode::from_name(name)
+            .map(|sc| keyboard.is_scancode_pressed(sc))
+            .unwrap_or(false);
+        let params = pipeline.params();
+        for i in 0..params.specs.len(){
+            if let Some((up, down)) = params.specs[i].keys.clone(){
+                let dir = pressed(&up) as i32 - pressed(&down) as i32;
+                if dir != 0 && !typing{
+                    params.adjust(i, dir as f32 * real_dt * 0.5);
+                    params_changed = true;
+                }
+            }
+        }
+        // save and print once done changing
+        if params_changed{
+            params_dirty = true;
+            need_refresh = true;
+        } else if params_dirty{
+            params_dirty = false;
+            params.print();
+            if let Some(path) = &conf.params_file{
+                if let Err(e) = params.save(path){
+                    println!("{}", e);
+                }
+            }
+        }
         need_refresh = need_refresh ||
-        if event_pump.keyboard_state().is_scancode_pressed(Scancode::Left){
-            play_t -= if playing { 1.0 / 15.0 } else { 1.0 / 30.0 };
-            lt = start.elapsed().as_millis() as f32 / 1000.0;
+        if typing{
+            false
+        } else if event_pump.keyboard_state().is_scancode_pressed(Scancode::Left){
+            clock.shift(if playing { -1.0 / 15.0 } else { -1.0 / 30.0 });
             true
         } else if event_pump.keyboard_state().is_scancode_pressed(Scancode::Right){
-            play_t += 1.0 / 30.0;
-            lt = start.elapsed().as_millis() as f32 / 1000.0;
+            clock.shift(1.0 / 30.0);
             true
         }else {
             false
         };
         // rebuild shader if needed
-        need_refresh = need_refresh || if streamer.is_dirty(){
-            println!("Frag: rebuilding shader.");
-            match streamer.build(false){
-                Ok(program) => {
-                    render_program = program;
-                    render_program.set_used();
-                    i_aspect.reload(&render_program);
-                    i_resolution.reload(&render_program);
-                    i_time.reload(&render_program);
-                    i_delta_time.reload(&render_program);
-                    i_frame.reload(&render_program);
-                    i_aspect.set_1f(cw as f32 / ch as f32);
-                    i_resolution.set_2f(cw as f32, ch as f32);
-                    true
-                },
-                Err(e) => {
-                    println!("Frag: could not rebuild shader: {}", e);
-                    false
-                },
-            }
-        } else {
-            false
-        };
+        need_refresh = pipeline.rebuild() || need_refresh;
         // render
         if need_refresh || playing{
-            unsafe{
-                // render to texture
-                gl::BindFramebuffer(gl::FRAMEBUFFER, canvas_fbo);
-                render_program.set_used();
-                gl::Viewport(0, 0, cw, ch);
-                gl::Clear(gl::COLOR_BUFFER_BIT);
-                gl::BindVertexArray(vao);
-                i_time.set_1f(play_t);
-                i_delta_time.set_1f(dt);
-                i_frame.set_1ui(frame);
-                gl::DrawArrays(gl::TRIANGLES, 0, 6);
-                //render to screen
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-                post_program.set_used();
-                gl::Viewport(0, 0, ww, wh);
-                gl::BindTexture(gl::TEXTURE_2D, canvas_tex);
-                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            let stepped = frame_drawn_at != Some(clock.time());
+            // with a fixed timestep iFrame follows the clock, like when rendering to file
+            frame = match clock.frame(){
+                Some(clock_frame) => clock_frame,
+                None if stepped && frame_drawn_at.is_some() => frame + 1,
+                None => frame,
+            };
+            let inputs = Inputs{
+                time: clock.time(),
+                delta_time: dt,
+                frame,
+                frame_rate: clock.framerate().unwrap_or(if dt > 0.0 { 1.0 / dt } else { 0.0 }),
+                mouse: mouse.uniform(),
+                date: Inputs::date(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()),
+            };
+            // only a new time advances feedback buffers, mouse moves, params and rebuilds while
+            // paused render the same frame again
+            if stepped{
+                pipeline.draw(&inputs, Some(context.screen_fbo()));
+                frame_drawn_at = Some(clock.time());
+            } else {
+                pipeline.redraw(&inputs, Some(context.screen_fbo()));
             }
-            window.gl_swap_window();
-            frame += 1;
+            // save before the timeline is drawn on top
+            if let Some(full_size) = save_requested.take(){
+                let saved = if full_size{
+                    save_still(&mut pipeline, &inputs, &conf.still, (ww, wh), conf.alpha)
+                } else {
+                    save_screen(&mut pipeline, &inputs, &conf.still, (ww, wh), conf.alpha)
+                };
+                match saved{
+                    Ok(path) => println!("Frag: saved {}.", path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            if show_timeline{
+                if let Err(e) = pipeline.draw_timeline(inputs.time, clock.loop_region(), context.screen_fbo()){
+                    println!("{}", e);
+                    show_timeline = false;
+                }
+            }
+            context.swap();
+            if conf.mouse_recording.is_some(){
+                mouse_track.record(inputs.time, inputs.mouse);
+            }
+            mouse.next_frame();
+            drawn += 1;
             if playing{
-                t = start.elapsed().as_millis() as f32 / 1000.0;
-                dt = t - lt;
-                play_t += dt;
+                dt = clock.advance();
             }
+            let t = start.elapsed().as_secs_f32();
             if t.floor() > sec{
-                print!("{}, ", frame - last_frames);
-                last_frames = frame;
+                print!("{}, ", drawn - last_frames);
+                last_frames = drawn;
                 sec = t.floor();
-                std::io::stdout().flush().strerr("Frag: could not flush stdout.")?;
+                let _ = std::io::stdout().flush();
             }
         }
     }
 
-    unsafe{
-        gl::DeleteFramebuffers(1, &canvas_fbo);
+    if let (true, Some(path)) = (params_dirty, &conf.params_file){
+        pipeline.params().save(path)?;
+    }
+
+    if let Some(path) = &conf.mouse_recording{
+        mouse_track.save(path)?;
+        println!("Frag: saved mouse track to {}.", path);
     }
 
     Ok(())
 }
 
-fn init_context(ww: i32, wh: i32)
-    -> Result<(sdl2::Sdl,sdl2::video::Window,sdl2::video::GLContext), String>
-{
-    let sdl_context = sdl2::init()?;//.expect("Frag: could not create SDL context.");
-    let video_subsystem = sdl_context.video()?;//.expect("Frag: could not get SDL video subsystem.");
+// jump to a time typed by the user
+fn jump(clock: &mut Clock, text: &str){
+    match parse_time(text){
+        Some(time) => {
+            clock.seek(time);
+            println!("Frag: time {:.3}.", clock.time());
+        },
+        None => println!("Frag: can not go to {:?}, type seconds like 12.5 or minutes like 1:02.5.", text.trim()),
+    }
+}
 
-    // window dimension must be the same or bigger as render dimensions, :/
-    let window = video_subsystem.window(":3", ww as u32, wh as u32)
-        .position_centered().opengl().build().strerr("Frag: could not create window.")?;
+fn print_loop(clock: &Clock){
+    match clock.loop_region(){
+        Some((start, end)) => println!("Frag: looping {:.3} - {:.3}.", start, end),
+        None => println!("Frag: not looping."),
+    }
+}
 
-    //needs to exist
-    let _gl_contex = window.gl_create_context().strerr("Frag: could not create GL context.")?;
+// render a still in tiles and write it, returns the path
+fn save_still(pipeline: &mut Pipeline, inputs: &Inputs, still: &StillConf, window: (i32, i32), alpha: bool)
+    -> Result<String, Error>
+{
+    let (w, h) = still.size(window);
+    println!("Frag: rendering {}x{} still.", w, h);
+    let pixels = pipeline.render_image(inputs, (w, h), still.supersample(), still.format().is_float())?;
+    still.write(inputs.time, w, h, &pixels, alpha)
+}
 
-    let gl_attr = video_subsystem.gl_attr();
-    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-    gl_attr.set_context_version(4, 5);
+// write the frame on screen, post processed again into a target that keeps alpha and floats
+fn save_screen(pipeline: &mut Pipeline, inputs: &Inputs, still: &StillConf, (ww, wh): (i32, i32), alpha: bool)
+    -> Result<String, Error>
+{
+    let fbo = pipeline.output_fbo()?;
+    pipeline.redraw_post(inputs, fbo);
+    still.write(inputs.time, ww, wh, &read_pixels(ww, wh, still.format().is_float()), alpha)
+}
 
-    gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+// OpenGl code stolen from these sources
+// https://nercury.github.io/rust/opengl/tutorial/2018/02/10/opengl-in-rust-from-scratch-03-compiling-shaders.html
+// https://nercury.github.io/rust/opengl/tutorial/2018/02/11/opengl-in-rust-from-scratch-04-triangle.html
+// https://learnopengl.com/Advanced-OpenGL/Framebuffers
 
-    Ok((sdl_context, window, _gl_contex))
+/// A fresh directory for the files of one test, unique per test and process
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> std::path::PathBuf{
+    let dir = std::env::temp_dir().join(format!("frag_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
 }
 
-fn init_programs(streamer: &mut ShaderStreamer) -> (Program, Program){
-    let render_program = match streamer.build(true){
-        Ok(program) => program,
-        Err(e) => {
-            println!("Frag: could not build program: {}", e);
-            Program::new(
-                RENDER_VERT_SRC,
-                &format!("{}{}", RENDER_FRAG_HEADER, RENDER_FRAG_STD_BODY), &[]
-            ).expect("Frag: could not create standard program.")
-        },
-    };
-    let post_program = Program::new(POST_VERT_SRC, POST_FRAG_SRC, &[])
-        .expect("Frag: could not create post program.");
-    (render_program, post_program)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    // Tests that render need EGL, like Mesa's llvmpipe, and fail without it.
+    // Set FRAG_SKIP_GL_TESTS to skip them on machines without EGL.
+    fn skip_gl() -> bool{
+        std::env::var_os("FRAG_SKIP_GL_TESTS").is_some()
+    }
 
-fn init_uniforms(render_program: &mut Program, cw: i32, ch: i32)
-    -> (Uniform, Uniform, Uniform, Uniform, Uniform)
-{
-    render_program.set_used();
-    let i_time = Uniform::new(render_program, "iTime").with_1f(0.0);
-    let i_delta_time = Uniform::new(render_program, "iDeltaTime").with_1f(0.0);
-    let i_frame = Uniform::new(render_program, "iFrame").with_1ui(0);
-    let i_aspect = Uniform::new(render_program, "iAspect").with_1f(cw as f32 / ch as f32);
-    let i_resolution = Uniform::new(render_program, "iResolution").with_2f(cw as f32, ch as f32);
-    (i_time, i_delta_time, i_frame, i_aspect, i_resolution)
-}
+    // Renders some frames offscreen and reads back the pixels of the last one
+    fn render_headless(mut conf: FragConf, body: &str, t: f32, frames: u32) -> Result<Vec<u8>, Error>{
+        let (w, h) = (conf.ww, conf.wh);
+        let context = Context::headless(w, h, conf.gl)?;
+        let streamer = conf.streamer.take().unwrap_or_default().with_str(body);
+        let mut pipeline = Pipeline::new(&mut conf, streamer)?;
+        for frame in 0..frames{
+            let inputs = Inputs{ time: t, frame, ..Default::default() };
+            pipeline.draw(&inputs, Some(context.screen_fbo()));
+        }
+        let mut buffer: Vec<u8> = vec![0; (w * h) as usize * 4];
+        unsafe{
+            gl::ReadPixels(
+                0, 0, w, h, gl::RGBA, gl::UNSIGNED_BYTE, buffer.as_mut_ptr() as *mut c_void
+            );
+        }
+        Ok(buffer)
+    }
 
-fn init_quad() -> gl::types::GLuint{
-    let vertices: Vec<f32> = vec![
-        -1., -1., 0., -1., 1., 0., 1., 1., 0., -1., -1., 0., 1., 1., 0., 1., -1., 0.
-    ];
-    let mut vbo: gl::types::GLuint = 0;
-    let mut vao: gl::types::GLuint = 0;
-
-    unsafe {
-        gl::GenBuffers(1, &mut vbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER, // target
-            // size of data in bytes
-            (vertices.len() * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-            vertices.as_ptr() as *const gl::types::GLvoid, // pointer to data
-            gl::STATIC_DRAW // usage
-        );
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        gl::GenVertexArrays(1, &mut vao);
-        gl::BindVertexArray(vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(
-            0, 3,
-            gl::FLOAT, gl::FALSE,
-            (3 * std::mem::size_of::<f32>()) as gl::types::GLint, std::ptr::null()
-        );
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        gl::BindVertexArray(0);
+    fn conf(w: u32, h: u32) -> FragConf{
+        FragConf::new().with_window_width(w).with_window_height(h)
     }
-    vao
-}
 
-fn init_rendertarget(cw: i32, ch: i32, pixelate: bool)
-    -> Result<(gl::types::GLuint, gl::types::GLuint), String>
-{
-    let mut canvas_fbo: gl::types::GLuint = 0;
-    let mut canvas_tex: gl::types::GLuint = 0;
-
-    unsafe{
-        gl::GenFramebuffers(1, &mut canvas_fbo);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, canvas_fbo);
-        gl::GenTextures(1, &mut canvas_tex);
-        gl::BindTexture(gl::TEXTURE_2D, canvas_tex);
-
-        gl::TexImage2D(
-            gl::TEXTURE_2D, 0, gl::RGB as i32, cw, ch, 0,
-            gl::RGB, gl::UNSIGNED_BYTE, std::ptr::null()
-        );
-        let filter = if pixelate { gl::NEAREST } else { gl::LINEAR } as i32;
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+    fn streamer(src: &str) -> ShaderStreamer{
+        ShaderStreamer::new().with_str(src)
+    }
+
+    fn close(a: u8, b: u8) -> bool{
+        (a as i32 - b as i32).abs() <= 1
+    }
+
+    #[test]
+    fn headless_solid_color() {
+        if skip_gl(){ return; }
+        let body = "void main(){ color = vec4(1.0, 0.0, 1.0, 1.0); }";
+        let buffer = render_headless(conf(16, 8), body, 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [255, 0, 255, 255]));
+    }
+
+    #[test]
+    fn headless_uniforms() {
+        if skip_gl(){ return; }
+        let body = "void main(){ color = vec4(iTime, iResolution / 100.0, 1.0); }";
+        let buffer = render_headless(conf(50, 25), body, 0.5, 1).unwrap();
+        assert!(close(buffer[0], 128));
+        assert!(close(buffer[1], 128));
+        assert!(close(buffer[2], 64));
+    }
+
+    #[test]
+    fn headless_builtins() {
+        if skip_gl(){ return; }
+        let body = "void main(){
+            float coord = distance(fragCoord, gl_FragCoord.xy) < 0.01 ? 1.0 : 0.0;
+            color = vec4(coord, iPixelSize.y * 2.0, float(iFrame) / iWindowResolution.y, 1.0);
+        }";
+        let buffer = render_headless(conf(8, 4), body, 0.0, 3).unwrap();
+        assert!(buffer.chunks(4).all(|p| p[0] == 255 && close(p[1], 128) && close(p[2], 128)));
+    }
+
+    #[test]
+    fn headless_buffer_pass() {
+        if skip_gl(){ return; }
+        let conf = conf(8, 8)
+            .with_buffer("BufferA", streamer("void main(){ color = vec4(0.0, 1.0, 0.0, 1.0); }"));
+        let body = "void main(){ color = texelFetch(BufferA, ivec2(gl_FragCoord.xy), 0).gbra; }";
+        let buffer = render_headless(conf, body, 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn headless_buffer_feedback() {
+        if skip_gl(){ return; }
+        let conf = conf(8, 8).with_buffer("BufferA", streamer("void main(){
+            color = texelFetch(iChannel0, ivec2(gl_FragCoord.xy), 0) + vec4(0.25);
+        }"));
+        let body = "void main(){ color = texelFetch(BufferA, ivec2(gl_FragCoord.xy), 0); }";
+        let buffer = render_headless(conf, body, 0.0, 3).unwrap();
+        assert!(close(buffer[0], 191));
+    }
+
+    #[test]
+    fn headless_hdr() {
+        if skip_gl(){ return; }
+        // feedback adds less than an 8 bit step every frame, the post pass tonemaps the sum
+        let hdr = conf(4, 4)
+            .with_canvas_format(CanvasFormat::Rgba16F)
+            .with_tonemap(Tonemap::Reinhard)
+            .with_buffer("BufferA", streamer("void main(){
+                color = texelFetch(iChannel0, ivec2(gl_FragCoord.xy), 0) + vec4(0.001);
+            }"));
+        let body = "void main(){
+            color = vec4(texelFetch(BufferA, ivec2(gl_FragCoord.xy), 0).r * 1000.0 / 3.0, 3.0, 0.0, 1.0);
+        }";
+        let buffer = render_headless(hdr, body, 0.0, 3).unwrap();
+        assert!(close(buffer[0], 128) && close(buffer[1], 191), "{:?}", &buffer[..4]);
+        let gray = conf(4, 4).with_canvas_format(CanvasFormat::R32F);
+        let buffer = render_headless(gray, "void main(){ color = vec4(0.5, 0.0, 1.0, 1.0); }", 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| close(p[0], 128) && p[0] == p[1] && p[1] == p[2]));
+    }
+
+    #[test]
+    fn headless_float_export() {
+        if skip_gl(){ return; }
+        let mut float = conf(4, 4).with_canvas_format(CanvasFormat::Rgba32F);
+        let context = Context::headless(4, 4, float.gl).unwrap();
+        let body = "void main(){ color = vec4(4.0, 0.5, -1.0, 0.25); }";
+        let mut pipeline = Pipeline::new(&mut float, streamer(body)).unwrap();
+        // values outside [0, 1] and alpha make it through the post pass
+        let pixels = pipeline.render_image(&Inputs::default(), (6, 2), 1, true).unwrap();
+        assert!(pixels.to_f32().chunks(4).all(|p| p == [4.0, 0.5, -1.0, 0.25]));
+        drop(pipeline);
+        drop(context);
+    }
+
+    #[test]
+    fn headless_post_streamer() {
+        if skip_gl(){ return; }
+        let conf = conf(16, 16).with_canvas_width(4).with_canvas_height(4)
+            .with_post_streamer(streamer("void main(){
+                color = vec4(1.0 - texture(iCanvas, uv).rgb, 1.0);
+                color.b = iWindowResolution.x / iResolution.x / 4.0;
+            }"));
+        let body = "void main(){ color = vec4(1.0, 0.0, 0.0, 1.0); }";
+        let buffer = render_headless(conf, body, 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [0, 255, 255, 255]));
+    }
 
-        gl::FramebufferTexture2D(
-            gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, canvas_tex, 0
+    #[test]
+    fn headless_gl_versions() {
+        if skip_gl(){ return; }
+        let body = "void main(){ color = vec4(0.0, 1.0, iResolution.x / 8.0, 1.0); }";
+        // scaled up, so the default post pass runs too
+        let gl33 = conf(8, 8).with_canvas_width(4).with_canvas_height(4).with_gl_version(3, 3);
+        let gles = conf(8, 8).with_canvas_width(4).with_canvas_height(4).with_gles_version(3, 0);
+        for conf in [gl33, gles]{
+            let buffer = render_headless(conf, body, 0.0, 1).unwrap();
+            assert!(buffer.chunks(4).all(|p| p == [0, 255, 128, 255] || p == [0, 255, 127, 255]));
+        }
+    }
+
+    #[test]
+    fn headless_custom_header() {
+        if skip_gl(){ return; }
+        let conf = conf(4, 4).with_glsl_version("330 core").with_streamer(
+            ShaderStreamer::new()
+                .with_header("out vec4 color;\n")
+                .with_extra_header("#define RED vec4(1.0, 0.0, 0.0, 1.0)\n")
         );
-        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE{
-            return Err("Frag: could not initialize canvas framebuffer.".to_string());
+        let buffer = render_headless(conf, "void main(){ color = RED; }", 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn headless_texture() {
+        if skip_gl(){ return; }
+        let path = test_dir("headless_texture").join("texture.png");
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(&path).unwrap()), 1, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.write_header().unwrap().write_image_data(&[0, 0, 255]).unwrap();
+        let conf = conf(4, 4)
+            .with_texture("blue", path.to_str().unwrap())
+            .with_post_streamer(streamer("void main(){
+                color = texture(iCanvas, uv) + texture(blue, uv);
+            }"));
+        let body = "void main(){ color = vec4(1.0, 0.0, 0.0, 1.0); }";
+        let buffer = render_headless(conf, body, 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [255, 0, 255, 255]));
+    }
+
+    #[test]
+    fn headless_custom_uniforms() {
+        if skip_gl(){ return; }
+        let handle = UniformHandle::new(UniformValue::Float(0.0));
+        let conf = conf(4, 4)
+            .with_uniform("tint", UniformValue::Vec3([0.0, 1.0, 0.0]))
+            .with_uniform("weights", UniformValue::FloatArray(vec![0.0, 0.0, 1.0]))
+            .with_uniform_handle("blue", &handle)
+            .with_uniform_fn("on", |t| UniformValue::Bool(t > 1.0));
+        handle.set(UniformValue::Float(1.0));
+        let body = "void main(){
+            color = vec4(tint.r + weights[2], tint.g, blue, on ? 1.0 : 0.0);
+        }";
+        let buffer = render_headless(conf, body, 2.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn headless_params() {
+        if skip_gl(){ return; }
+        let path = test_dir("headless_params").join("params.txt");
+        std::fs::write(&path, "green 1\n").unwrap();
+        let conf = conf(4, 4).with_params_file(path.to_str().unwrap());
+        let body = "
+uniform float red; // @range(0, 2) @default(1)
+uniform float green; // @range(0, 1) @key(G/H)
+void main(){ color = vec4(red, green, 0.0, 1.0); }";
+        let buffer = render_headless(conf, body, 0.0, 1).unwrap();
+        assert!(buffer.chunks(4).all(|p| p == [255, 255, 0, 255]));
+    }
+
+    #[test]
+    fn headless_shadertoy() {
+        if skip_gl(){ return; }
+        let conf = conf(8, 8)
+            .with_buffer("BufferA", ShaderStreamer::shadertoy().with_str("
+void mainImage(out vec4 fragColor, in vec2 fragCoord){ fragColor = vec4(0.0, 1.0, 0.0, 1.0); }"))
+            .with_streamer(ShaderStreamer::shadertoy());
+        let body = "
+void mainImage(out vec4 fragColor, in vec2 fragCoord){
+    vec4 a = texelFetch(iChannel0, ivec2(fragCoord), 0);
+    float unbound = texture(iChannel1, vec2(0.5)).r;
+    fragColor = vec4(fragCoord.x / iResolution.x, a.g - unbound, float(iFrame) / 4.0, 1.0);
+}";
+        let buffer = render_headless(conf, body, 0.0, 3).unwrap();
+        assert!(close(buffer[0], 16));
+        assert_eq!(&buffer[1..2], &[255]);
+        assert!(close(buffer[2], 128));
+    }
+
+    #[test]
+    fn headless_timeline() {
+        if skip_gl(){ return; }
+        let (w, h) = (20, 10);
+        let context = Context::headless(w, h, GlVersion::default()).unwrap();
+        let mut conf = conf(w as u32, h as u32);
+        let mut pipeline = Pipeline::new(&mut conf, streamer("void main(){ color = vec4(0.0, 1.0, 0.0, 1.0); }")).unwrap();
+        pipeline.draw(&Inputs::default(), Some(context.screen_fbo()));
+        // time 5 is in the middle of a bar of 10 seconds, the loop region in the first quarter
+        pipeline.draw_timeline(5.0, Some((0.0, 2.5)), context.screen_fbo()).unwrap();
+        let mut buffer: Vec<u8> = vec![0; (w * h) as usize * 4];
+        unsafe{
+            gl::ReadPixels(0, 0, w, h, gl::RGBA, gl::UNSIGNED_BYTE, buffer.as_mut_ptr() as *mut c_void);
         }
+        let pixel = |x: i32, y: i32| &buffer[((y * w + x) * 4) as usize..((y * w + x) * 4 + 3) as usize];
+        assert_eq!(pixel(10, 0), [255, 255, 255]);
+        assert_eq!(pixel(10, h - 1), [0, 255, 0]);
+        assert!(pixel(2, 0)[2] > pixel(15, 0)[2]);
+        assert!(pixel(15, 0)[1] < 255);
     }
 
-    Ok((canvas_fbo, canvas_tex))
-}
+    #[test]
+    fn ffmpeg_audio_args() {
+        let conf = conf(4, 4).into_ffmpeg_renderer()
+            .with_framerate(25)
+            .with_start(50)
+            .with_length(100)
+            .with_output("out.mp4");
+        assert!(!conf.args(None).iter().any(|arg| arg == "-map"));
+        let args = conf.with_audio("music.ogg").with_audio_codec("libopus").with_audio_bitrate(128).args(None);
+        let args = args.join(" ");
+        assert!(args.contains("-i - -ss 2 -i music.ogg -map 0:v:0 -map 1:a:0 -c:a libopus -b:a 128k -t 4 "));
+        assert!(args.ends_with(" out.mp4"));
+    }
 
-// OpenGl code stolen from these sources
-// https://nercury.github.io/rust/opengl/tutorial/2018/02/10/opengl-in-rust-from-scratch-03-compiling-shaders.html
-// https://nercury.github.io/rust/opengl/tutorial/2018/02/11/opengl-in-rust-from-scratch-04-triangle.html
-// https://learnopengl.com/Advanced-OpenGL/Framebuffers
+    #[test]
+    fn ffmpeg_codecs() {
+        let video = |codec: Codec, output: &str, alpha: bool| conf(4, 4).with_alpha(alpha).into_ffmpeg_renderer()
+            .with_codec(codec)
+            .with_output(output);
+        assert!(matches!(video(Codec::H264, "out.mp4", true).validate(), Err(Error::Config(_))));
+        assert!(matches!(video(Codec::ProRes4444, "out.mp4", true).validate(), Err(Error::Config(_))));
+        assert!(matches!(video(Codec::Vp9, "out.mp4", true).validate(), Err(Error::Config(_))));
+        assert!(video(Codec::Vp9, "out.mp4", false).validate().is_ok());
+        let prores = video(Codec::ProRes4444, "out.MOV", true);
+        assert!(prores.validate().is_ok());
+        assert!(prores.args(None).join(" ").contains("-c:v prores_ks -profile:v 4444 -pix_fmt yuva444p10le -vf vflip"));
+        let vp9 = video(Codec::Vp9, "out.webm", true).args(None).join(" ");
+        assert!(vp9.contains("-c:v libvpx-vp9 -crf 20 -b:v 0 -pix_fmt yuva420p"));
+        assert!(video(Codec::Qtrle, "out.mov", false).args(None).join(" ").contains("-pix_fmt rgb24"));
+        let h264 = video(Codec::H264, "out.mp4", false).args(None).join(" ");
+        assert!(h264.contains("-c:v libx264 -preset medium -tune film -crf 20 -pix_fmt yuv420p"));
+        let gif = video(Codec::Gif, "out.gif", false).args(None).join(" ");
+        assert!(gif.contains("-c:v gif -vf vflip,split[a][b];[a]palettegen[p];[b][p]paletteuse out.gif"));
+    }
+
+    #[test]
+    fn ffmpeg_codec_options() {
+        let video = |codec: Codec, output: &str| conf(4, 4).into_ffmpeg_renderer().with_codec(codec).with_output(output);
+        let invalid = |conf: FFmpegConf| matches!(conf.validate(), Err(Error::Config(_)));
+        // clamped to the maximum of the codec
+        assert!(video(Codec::H264, "out.mp4").with_crf(60).args(None).join(" ").contains("-crf 51 "));
+        assert!(video(Codec::Av1Svt, "out.mkv").with_crf(99).args(None).join(" ").contains("-crf 63 "));
+        assert!(invalid(video(Codec::Av1Svt, "out.mkv").with_pixel_format(PixelFormat::Yuv444p)));
+        assert!(invalid(video(Codec::ProRes422, "out.mov").with_bitrate(5000)));
+        assert!(invalid(video(Codec::H264, "out.mp4").with_two_pass(true)));
+        assert!(invalid(video(Codec::Av1Svt, "out.mp4").with_bitrate(5000).with_two_pass(true)));
+        assert!(invalid(video(Codec::H265, "out.mp4").with_tune(Tune::Film)));
+        assert!(invalid(video(Codec::Vp9, "out.webm").with_audio("music.ogg")));
+        assert!(video(Codec::Vp9, "out.webm").with_audio("music.ogg").with_audio_codec("libopus").validate().is_ok());
+        assert!(invalid(video(Codec::Gif, "out.gif").with_audio("music.ogg")));
+        assert!(invalid(video(Codec::Ffv1, "out.mp4")));
+        assert!(invalid(video(Codec::ProRes422, "out.mp4")));
+        assert!(invalid(video(Codec::H264, "out.mp4").with_length(0)));
+        // unknown, missing and uncommon extensions are left to ffmpeg, or a forced format
+        for output in ["out.avi", "out.ts", "out.m4v", "out", "out.xyz"]{
+            assert!(video(Codec::H264, output).validate().is_ok(), "{}", output);
+        }
+        assert!(invalid(video(Codec::H264, "out.webm")));
+        assert!(video(Codec::H264, "out.webm").with_extra_args(&["-f", "matroska"]).validate().is_ok());
+        // 10 bit H.265 at a bitrate in two passes
+        let x265 = video(Codec::H265, "out.mkv")
+            .with_pixel_format(PixelFormat::Yuv420p10)
+            .with_tune(Tune::Grain)
+            .with_bitrate(8000)
+            .with_two_pass(true)
+            .with_audio("music.ogg")
+            .with_extra_args(&["-g", "60"]);
+        assert!(x265.validate().is_ok());
+        let first = x265.args(Some(1)).join(" ");
+        assert!(first.contains("-c:v libx265 -preset medium -tune grain -b:v 8000k -pix_fmt yuv420p10le"));
+        assert!(first.contains("-x265-params pass=1:stats=out.mkv.2pass.log"));
+        assert!(first.ends_with("-g 60 -an -f null /dev/null") || cfg!(windows));
+        assert!(!first.contains("music.ogg"));
+        let second = x265.args(Some(2)).join(" ");
+        assert!(second.contains("music.ogg") && second.contains("pass=2") && second.ends_with("-g 60 out.mkv"));
+        let vp9 = video(Codec::Vp9, "out.webm").with_bitrate(2000).with_two_pass(true).args(Some(2)).join(" ");
+        assert!(vp9.contains("-b:v 2000k -pix_fmt yuv420p -pass 2 -passlogfile out.webm.2pass"));
+        let ffv1 = video(Codec::Ffv1, "out.mkv").args(None).join(" ");
+        assert!(ffv1.contains("-c:v ffv1 -level 3 -pix_fmt bgr0"));
+    }
+
+    #[test]
+    fn headless_alpha() {
+        if skip_gl(){ return; }
+        let dir = test_dir("headless_alpha");
+        let pattern = dir.join("frame_#.png").to_string_lossy().into_owned();
+        // scaled, so through the post pass
+        let result = conf(4, 4)
+            .with_canvas_width(2)
+            .with_canvas_height(2)
+            .with_alpha(true)
+            .with_headless(true)
+            .with_streamer(streamer("void main(){ color = vec4(1.0, 0.0, 0.0, 0.25); }"))
+            .into_image_sequence_renderer()
+            .with_length(1)
+            .with_output(&pattern)
+            .render();
+        result.unwrap();
+        let mut reader = png::Decoder::new(File::open(dir.join("frame_0.png")).unwrap()).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert!(buffer.chunks(4).all(|p| p[0] == 255 && close(p[3], 64)), "{:?}", buffer);
+    }
+
+    #[test]
+    fn headless_audio() {
+        if skip_gl(){ return; }
+        let path = test_dir("headless_audio").join("audio.wav");
+        let spec = hound::WavSpec{
+            channels: 1, sample_rate: 44100, bits_per_sample: 32, sample_format: hound::SampleFormat::Float
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..44100{
+            writer.write_sample((i as f32 / 44100.0 * 100.0 * std::f32::consts::TAU).sin()).unwrap();
+        }
+        writer.finalize().unwrap();
+        let conf = conf(4, 4).with_audio(path.to_str().unwrap());
+        let body = "void main(){
+            color = vec4(texelFetch(iAudio, ivec2(5, 0), 0).r, iBass, texelFetch(iAudio, ivec2(0, 1), 0).r, 1.0);
+        }";
+        let buffer = render_headless(conf, body, 0.5, 1).unwrap();
+        assert!(buffer[0] > 200);
+        assert!(buffer[1] > 200);
+        assert!(buffer[2] > 0);
+    }
+
+    #[test]
+    fn headless_sample_rate() {
+        if skip_gl(){ return; }
+        let path = test_dir("headless_sample_rate").join("audio.wav");
+        let spec = hound::WavSpec{
+            channels: 1, sample_rate: 22050, bits_per_sample: 16, sample_format: hound::SampleFormat::Int
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..22050{
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let body = "void mainImage(out vec4 fragColor, in vec2 fragCoord){
+            fragColor = vec4(iSampleRate / 44100.0, 0.0, 0.0, 1.0);
+        }";
+        let shadertoy = || conf(4, 4).with_streamer(ShaderStreamer::shadertoy());
+        let buffer = render_headless(shadertoy(), body, 0.0, 1).unwrap();
+        assert_eq!(buffer[0], 255);
+        let buffer = render_headless(shadertoy().with_audio(path.to_str().unwrap()), body, 0.0, 1).unwrap();
+        assert!(close(buffer[0], 128));
+    }
+
+    #[test]
+    fn headless_image_sequence() {
+        if skip_gl(){ return; }
+        let dir = test_dir("headless_image_sequence");
+        let pattern = dir.join("frame_###.png").to_string_lossy().into_owned();
+        let result = conf(2, 2)
+            .with_headless(true)
+            .with_streamer(streamer("void main(){ color = vec4(iTime, float(iFrame) / 4.0, 0.0, 1.0); }"))
+            .into_image_sequence_renderer()
+            .with_framerate(10)
+            .with_start(2)
+            .with_length(3)
+            .with_output(&pattern)
+            .with_format(ImageFormat::Png16)
+            .render();
+        result.unwrap();
+        let names = ["frame_002.png", "frame_003.png", "frame_004.png"];
+        assert!(names.iter().all(|name| dir.join(name).exists()));
+        assert!(!dir.join("frame_005.png").exists());
+        // the last frame is at 0.4 seconds and frame 4, like live with the same fixed timestep
+        let mut reader = png::Decoder::new(File::open(dir.join(names[2])).unwrap()).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        assert_eq!(reader.info().bit_depth, png::BitDepth::Sixteen);
+        let red = u16::from_be_bytes([buffer[0], buffer[1]]);
+        let green = u16::from_be_bytes([buffer[2], buffer[3]]);
+        assert!((red as f32 / 65535.0 - 0.4).abs() < 0.01);
+        assert!((green as f32 / 65535.0 - 1.0).abs() < 0.01);
+        let mut clock = Clock::new(Some(10), false);
+        for _ in 0..4{
+            clock.advance();
+        }
+        assert_eq!(clock.frame(), Some(4));
+        assert!((red as f32 / 65535.0 - clock.time()).abs() < 0.01);
+    }
+
+    #[test]
+    fn empty_image_sequence() {
+        let dir = test_dir("empty_image_sequence");
+        let result = conf(2, 2)
+            .with_headless(true)
+            .with_streamer(streamer("void main(){ color = vec4(1.0); }"))
+            .into_image_sequence_renderer()
+            .with_length(0)
+            .with_output(&dir.join("frame_###.png").to_string_lossy())
+            .render();
+        // rejected like a video of no frames, before anything is created
+        assert!(matches!(result, Err(Error::Config(message)) if message.contains("length")));
+        assert!(!dir.join("frame_000.png").exists());
+    }
+
+    #[test]
+    fn headless_failed_context() {
+        if skip_gl(){ return; }
+        // too large for the screen framebuffer, the context is released again
+        let result = Context::headless(i32::MAX, 4, GlVersion::default());
+        assert!(matches!(result, Err(Error::Gl(_))));
+        let context = Context::headless(4, 4, GlVersion::default()).unwrap();
+        assert_ne!(context.screen_fbo(), 0);
+    }
+
+    #[test]
+    fn headless_redraw_keeps_feedback() {
+        if skip_gl(){ return; }
+        let mut feedback = conf(4, 4).with_buffer("BufferA", streamer("void main(){
+            color = texelFetch(iChannel0, ivec2(gl_FragCoord.xy), 0) + vec4(0.25);
+        }"));
+        let context = Context::headless(4, 4, feedback.gl).unwrap();
+        let body = "void main(){ color = texelFetch(BufferA, ivec2(gl_FragCoord.xy), 0); }";
+        let mut pipeline = Pipeline::new(&mut feedback, streamer(body)).unwrap();
+        pipeline.draw(&Inputs::default(), Some(context.screen_fbo()));
+        pipeline.redraw(&Inputs::default(), Some(context.screen_fbo()));
+        pipeline.redraw(&Inputs::default(), Some(context.screen_fbo()));
+        assert!(close(read_screen(4, 4)[0], 64));
+        pipeline.draw(&Inputs::default(), Some(context.screen_fbo()));
+        assert!(close(read_screen(4, 4)[0], 128));
+    }
 
-#[cfg(test)]
-mod tests {
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn headless_tiled_still() {
+        if skip_gl(){ return; }
+        let mut tiled = conf(4, 4);
+        let context = Context::headless(4, 4, tiled.gl).unwrap();
+        let body = "void main(){ color = vec4(fragCoord / iResolution, uv.x / iAspect + 0.5, 1.0); }";
+        let mut pipeline = Pipeline::new(&mut tiled, streamer(body)).unwrap();
+        // 3x2 tiles with cropped edges, every subpixel averaged to the center of the pixel
+        for supersample in [1, 2]{
+            let pixels = pipeline.render_image(&Inputs::default(), (10, 6), supersample, true).unwrap().to_f32();
+            for (i, px) in pixels.chunks(4).enumerate(){
+                let (x, y) = ((i % 10) as f32 + 0.5, (i / 10) as f32 + 0.5);
+                assert!((px[0] - x / 10.0).abs() < 0.01, "{} {:?}", i, px);
+                assert!((px[1] - y / 6.0).abs() < 0.01, "{} {:?}", i, px);
+                assert!((px[2] - px[0]).abs() < 0.01, "{} {:?}", i, px);
+            }
+        }
+        drop(pipeline);
+        drop(context);
+        let path = test_dir("headless_tiled_still").join("still_{time}.png").to_string_lossy().into_owned();
+        let path = conf(4, 4)
+            .with_headless(true)
+            .with_streamer(streamer(body))
+            .with_still(StillConf::new().with_size(9, 5).with_supersample(3).with_output(&path))
+            .render_still(0.5)
+            .unwrap();
+        assert!(path.ends_with("still_0.500.png"));
+        let reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (9, 5));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn read_screen(w: i32, h: i32) -> Vec<u8>{
+        let mut buffer: Vec<u8> = vec![0; (w * h) as usize * 4];
+        unsafe{
+            gl::ReadPixels(0, 0, w, h, gl::RGBA, gl::UNSIGNED_BYTE, buffer.as_mut_ptr() as *mut c_void);
+        }
+        buffer
+    }
+
+    #[test]
+    fn headless_still_keeps_feedback() {
+        if skip_gl(){ return; }
+        let mut feedback = conf(4, 4).with_buffer("BufferA", streamer("void main(){
+            color = texelFetch(iChannel0, ivec2(gl_FragCoord.xy), 0) + vec4(0.25);
+        }"));
+        let context = Context::headless(4, 4, feedback.gl).unwrap();
+        let body = "void main(){ color = texelFetch(BufferA, ivec2(gl_FragCoord.xy), 0); }";
+        let mut pipeline = Pipeline::new(&mut feedback, streamer(body)).unwrap();
+        pipeline.draw(&Inputs::default(), Some(context.screen_fbo()));
+        // every one of the 9 tiles is the frame after the one on screen
+        let pixels = pipeline.render_image(&Inputs::default(), (12, 12), 1, true).unwrap().to_f32();
+        assert!(pixels.iter().all(|c| (c - 0.5).abs() < 0.01), "{:?}", pixels);
+        pipeline.draw(&Inputs::default(), Some(context.screen_fbo()));
+        assert!(close(read_screen(4, 4)[0], 128));
+    }
+
+    #[test]
+    fn headless_pixelated_still() {
+        if skip_gl(){ return; }
+        // a 2x2 canvas in a 4x4 window, so a 4x4 canvas for an 8x8 image, scaled up without filtering
+        let mut pixelated = conf(4, 4).with_canvas_width(2).with_canvas_height(2).with_pixelate(true);
+        let context = Context::headless(4, 4, pixelated.gl).unwrap();
+        let body = "void main(){ color = vec4(fragCoord / iResolution, iResolution.x / 8.0, 1.0); }";
+        let mut pipeline = Pipeline::new(&mut pixelated, streamer(body)).unwrap();
+        let pixels = pipeline.render_image(&Inputs::default(), (8, 8), 1, true).unwrap().to_f32();
+        for (i, px) in pixels.chunks(4).enumerate(){
+            let (x, y) = ((i % 8 / 2) as f32 + 0.5, (i / 8 / 2) as f32 + 0.5);
+            assert!((px[0] - x / 4.0).abs() < 0.01, "{} {:?}", i, px);
+            assert!((px[1] - y / 4.0).abs() < 0.01, "{} {:?}", i, px);
+            assert!((px[2] - 0.5).abs() < 0.01, "{} {:?}", i, px);
+        }
+        // the screen is scaled like before
+        pipeline.draw(&Inputs::default(), Some(context.screen_fbo()));
+        let screen = read_screen(4, 4);
+        assert!(close(screen[0], 64) && close(screen[1], 64) && close(screen[2], 64));
+        assert!(close(screen[3 * 4], 191));
+    }
+
+    // compile files and return the location of the first error
+    fn first_error(dir: &Path, files: &[(&str, &str)]) -> Option<(String, usize)>{
+        let mut streamer = ShaderStreamer::new();
+        let mut paths = Vec::new();
+        for (name, content) in files{
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            paths.push(path.to_str().unwrap().to_string());
+            streamer = streamer.with_file(paths.last().unwrap());
+        }
+        match streamer.build(true){
+            Err(Error::Compile(diagnostics)) => {
+                let first = &diagnostics[0];
+                assert_eq!(first.severity, Severity::Error);
+                let file = first.file.as_deref().unwrap();
+                let name = Path::new(file).file_name().unwrap().to_str().unwrap().to_string();
+                Some((name, first.line.unwrap()))
+            },
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => None,
+        }
+    }
+
+    #[test]
+    fn headless_compile_diagnostics() {
+        if skip_gl(){ return; }
+        let _context = Context::headless(4, 4, GlVersion::default()).unwrap();
+        let dir = test_dir("headless_compile_diagnostics");
+        let main = "void main(){\n    color = vec4(nope);\n}\n";
+        assert_eq!(first_error(&dir, &[("frag_test_main.glsl", main)]), Some(("frag_test_main.glsl".to_string(), 2)));
+        // no trailing newline in the first file
+        let lib = "float f(){ return 1.0; }\nfloat g(){ return 2.0; }";
+        assert_eq!(
+            first_error(&dir, &[("frag_test_lib.glsl", lib), ("frag_test_main.glsl", main)]),
+            Some(("frag_test_main.glsl".to_string(), 2))
+        );
+        // crlf and empty files
+        let crlf = "float f(){ return 1.0; }\r\n\r\nfloat g(){ return nope; }\r\n";
+        assert_eq!(
+            first_error(&dir, &[("frag_test_empty.glsl", ""), ("frag_test_crlf.glsl", crlf), ("frag_test_main.glsl", main)]),
+            Some(("frag_test_crlf.glsl".to_string(), 3))
+        );
+        assert_eq!(
+            first_error(&dir, &[("frag_test_crlf_ok.glsl", "float f(){ return 1.0; }\r\n"), ("frag_test_empty.glsl", ""), ("frag_test_main.glsl", main)]),
+            Some(("frag_test_main.glsl".to_string(), 2))
+        );
+    }
+
+    #[test]
+    fn headless_include_diagnostics() {
+        if skip_gl(){ return; }
+        let _context = Context::headless(4, 4, GlVersion::default()).unwrap();
+        let dir = test_dir("headless_include_diagnostics");
+        std::fs::write(
+            dir.join("frag_test_inc.glsl"),
+            "float f(){\n    return nope;\n}\n"
+        ).unwrap();
+        let main = "#include \"frag_test_inc.glsl\"\nvoid main(){ color = vec4(f()); }\n";
+        assert_eq!(
+            first_error(&dir, &[("frag_test_inc_main.glsl", main)]),
+            Some(("frag_test_inc.glsl".to_string(), 2))
+        );
+        let main = "#include \"frag_test_inc_ok.glsl\"\n\nvoid main(){ color = vec4(nope); }\n";
+        std::fs::write(dir.join("frag_test_inc_ok.glsl"), "float f(){ return 1.0; }").unwrap();
+        assert_eq!(
+            first_error(&dir, &[("frag_test_inc_main.glsl", main)]),
+            Some(("frag_test_inc_main.glsl".to_string(), 3))
+        );
     }
 }
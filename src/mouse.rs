@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::{ BufRead, BufReader, BufWriter, Write };
+
+use crate::error::Error;
+
+/// Mouse state with Shadertoy semantics, in canvas pixels with y up.
+/// xy: position while the left button is down, zw: position of the last click.
+/// z is negative when the button is up, w is only positive in the frame of the click.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Mouse{
+    pos: [f32; 2],
+    click: [f32; 2],
+    down: bool,
+    clicked: bool,
+}
+
+impl Mouse{
+    /// Window coordinates (y down) to canvas coordinates (y up)
+    pub fn to_canvas(x: i32, y: i32, (cw, ch, ww, wh): (i32, i32, i32, i32)) -> [f32; 2]{
+        [
+            x as f32 * cw as f32 / ww as f32,
+            (wh - y) as f32 * ch as f32 / wh as f32,
+        ]
+    }
+
+    pub fn motion(&mut self, pos: [f32; 2]){
+        if self.down{
+            self.pos = pos;
+        }
+    }
+
+    pub fn press(&mut self, pos: [f32; 2]){
+        self.pos = pos;
+        self.click = pos;
+        self.down = true;
+        self.clicked = true;
+    }
+
+    pub fn release(&mut self){
+        self.down = false;
+    }
+
+    /// Call after every rendered frame, a click only lasts one frame
+    pub fn next_frame(&mut self){
+        self.clicked = false;
+    }
+
+    pub fn uniform(&self) -> [f32; 4]{
+        [
+            self.pos[0],
+            self.pos[1],
+            if self.down { self.click[0] } else { -self.click[0] },
+            if self.clicked { self.click[1] } else { -self.click[1] },
+        ]
+    }
+}
+
+/// iMouse values over time, recorded in live mode and replayed when rendering to file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct MouseTrack{
+    samples: Vec<(f32, [f32; 4])>,
+}
+
+impl MouseTrack{
+    /// Adds a sample if the value changed since the last one
+    pub fn record(&mut self, t: f32, mouse: [f32; 4]){
+        if self.samples.last().map(|(_, last)| *last != mouse).unwrap_or(true){
+            self.samples.push((t, mouse));
+        }
+    }
+
+    /// Value of the latest sample at or before t
+    pub fn at(&self, t: f32) -> [f32; 4]{
+        self.samples.iter()
+            .filter(|(st, _)| *st <= t)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, mouse)| *mouse)
+            .unwrap_or_default()
+    }
+
+    /// One sample per line: time x y z w
+    pub fn save(&self, path: &str) -> Result<(), Error>{
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        let mut w = BufWriter::new(file);
+        let mut samples = self.samples.clone();
+        samples.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        for (t, [x, y, z, ww]) in samples{
+            writeln!(w, "{} {} {} {} {}", t, x, y, z, ww).map_err(|e| Error::io(path, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self, Error>{
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut samples = Vec::new();
+        for line in BufReader::new(file).lines(){
+            let line = line.map_err(|e| Error::io(path, e))?;
+            let values = line.split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| Error::parse(path, e))?;
+            match values.as_slice(){
+                [] => {},
+                [t, x, y, z, w] => samples.push((*t, [*x, *y, *z, *w])),
+                _ => return Err(Error::parse(path, format!("invalid line: {}", line))),
+            }
+        }
+        Ok(Self{ samples })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_to_canvas() {
+        let size = (320, 180, 1600, 900);
+        assert_eq!(Mouse::to_canvas(0, 900, size), [0.0, 0.0]);
+        assert_eq!(Mouse::to_canvas(800, 0, size), [160.0, 180.0]);
+    }
+
+    #[test]
+    fn shadertoy_semantics() {
+        let mut mouse = Mouse::default();
+        mouse.motion([5.0, 5.0]);
+        assert_eq!(mouse.uniform(), [0.0, 0.0, -0.0, -0.0]);
+        mouse.press([10.0, 20.0]);
+        assert_eq!(mouse.uniform(), [10.0, 20.0, 10.0, 20.0]);
+        mouse.next_frame();
+        mouse.motion([30.0, 40.0]);
+        assert_eq!(mouse.uniform(), [30.0, 40.0, 10.0, -20.0]);
+        mouse.release();
+        mouse.motion([50.0, 60.0]);
+        assert_eq!(mouse.uniform(), [30.0, 40.0, -10.0, -20.0]);
+    }
+
+    #[test]
+    fn track_roundtrip() {
+        let mut track = MouseTrack::default();
+        track.record(0.5, [1.0, 2.0, 3.0, 4.0]);
+        track.record(0.6, [1.0, 2.0, 3.0, 4.0]);
+        track.record(1.0, [5.0, 6.0, 7.0, -8.0]);
+        assert_eq!(track.samples.len(), 2);
+        assert_eq!(track.at(0.0), [0.0; 4]);
+        assert_eq!(track.at(0.7), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(track.at(2.0), [5.0, 6.0, 7.0, -8.0]);
+        let path = crate::test_dir("track_roundtrip").join("mouse.txt");
+        let path = path.to_str().unwrap();
+        track.save(path).unwrap();
+        assert_eq!(MouseTrack::load(path).unwrap(), track);
+    }
+}
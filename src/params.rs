@@ -0,0 +1,201 @@
+use std::fs::File;
+use std::io::{ BufRead, BufReader, BufWriter, Write };
+
+use crate::error::Error;
+
+/// A tunable float uniform, annotated in the shader source like:
+/// `uniform float speed; // @range(0, 10) @default(2) @key(Q/A)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamSpec{
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    /// Key names to increase and decrease the value
+    pub keys: Option<(String, String)>,
+}
+
+/// Finds all annotated float uniforms in the source
+pub(crate) fn parse_params(source: &str) -> Vec<ParamSpec>{
+    source.lines().filter_map(parse_param).collect()
+}
+
+fn parse_param(line: &str) -> Option<ParamSpec>{
+    let (code, comment) = line.split_once("//")?;
+    let mut words = code.split(|c: char| c.is_whitespace() || c == ';' || c == '=')
+        .filter(|w| !w.is_empty());
+    if (words.next(), words.next()) != (Some("uniform"), Some("float")){
+        return None;
+    }
+    let name = words.next()?.to_string();
+    let range = annotation(comment, "range");
+    let keys = annotation(comment, "key");
+    if range.is_none() && keys.is_none(){
+        return None;
+    }
+    let (min, max) = match range{
+        Some(args) => {
+            let (min, max) = args.split_once(',')?;
+            (min.trim().parse().ok()?, max.trim().parse().ok()?)
+        },
+        None => (0.0, 1.0),
+    };
+    let default = annotation(comment, "default")
+        .and_then(|d| d.trim().parse().ok())
+        .unwrap_or(min);
+    let keys = keys.and_then(|k| k.split_once('/'))
+        .map(|(up, down)| (up.trim().to_string(), down.trim().to_string()));
+    Some(ParamSpec{ name, min, max, default, keys })
+}
+
+// the arguments of @tag(...)
+fn annotation<'a>(comment: &'a str, tag: &str) -> Option<&'a str>{
+    let start = comment.find(&format!("@{}(", tag))? + tag.len() + 2;
+    let len = comment[start..].find(')')?;
+    Some(&comment[start..start + len])
+}
+
+/// Current values of all params, can be saved to and loaded from a sidecar file
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Params{
+    pub specs: Vec<ParamSpec>,
+    values: Vec<(String, f32)>,
+    selected: usize,
+}
+
+impl Params{
+    /// Use new specs, keeps the values of params that already existed
+    pub fn update_specs(&mut self, specs: Vec<ParamSpec>){
+        for spec in &specs{
+            if !self.values.iter().any(|(name, _)| name == &spec.name){
+                self.values.push((spec.name.clone(), spec.default));
+            }
+        }
+        self.specs = specs;
+        self.selected = self.selected.min(self.specs.len().saturating_sub(1));
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32>{
+        self.values.iter().find(|(n, _)| n == name).map(|(_, v)| *v)
+    }
+
+    /// Values of all current params, in the order of the specs
+    pub fn values(&self) -> Vec<(String, f32)>{
+        self.specs.iter()
+            .map(|spec| (spec.name.clone(), self.get(&spec.name).unwrap_or(spec.default)))
+            .collect()
+    }
+
+    /// Changes a param by a fraction of its range, clamped to the range
+    pub fn adjust(&mut self, index: usize, fraction: f32){
+        let spec = match self.specs.get(index){
+            Some(spec) => spec,
+            None => return,
+        };
+        let old = self.get(&spec.name).unwrap_or(spec.default);
+        let new = (old + (spec.max - spec.min) * fraction).clamp(spec.min, spec.max);
+        match self.values.iter_mut().find(|(n, _)| n == &spec.name){
+            Some((_, v)) => *v = new,
+            None => self.values.push((spec.name.clone(), new)),
+        }
+    }
+
+    pub fn selected(&self) -> usize{
+        self.selected
+    }
+
+    /// Select the next param for mouse dragging
+    pub fn select_next(&mut self){
+        if !self.specs.is_empty(){
+            self.selected = (self.selected + 1) % self.specs.len();
+        }
+    }
+
+    pub fn print(&self){
+        let values = self.values().iter().enumerate()
+            .map(|(i, (name, v))| {
+                let mark = if i == self.selected { "*" } else { "" };
+                format!("{}{} = {}", mark, name, v)
+            })
+            .collect::<Vec<_>>();
+        println!("Frag: params: {}", values.join(", "));
+    }
+
+    /// One param per line: name value
+    pub fn save(&self, path: &str) -> Result<(), Error>{
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        let mut w = BufWriter::new(file);
+        for (name, value) in &self.values{
+            writeln!(w, "{} {}", name, value).map_err(|e| Error::io(path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Values from the file overwrite the current ones
+    pub fn load(&mut self, path: &str) -> Result<(), Error>{
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        for line in BufReader::new(file).lines(){
+            let line = line.map_err(|e| Error::io(path, e))?;
+            let (name, value) = match line.split_once(' '){
+                Some((name, value)) => (name, value.trim()),
+                None if line.trim().is_empty() => continue,
+                None => return Err(Error::parse(path, format!("invalid line: {}", line))),
+            };
+            let value = value.parse::<f32>()
+                .map_err(|e| Error::parse(path, e))?;
+            match self.values.iter_mut().find(|(n, _)| n == name){
+                Some((_, v)) => *v = value,
+                None => self.values.push((name.to_string(), value)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_annotations() {
+        let source = "
+uniform float speed; // @range(0, 10) @key(Q/A)
+uniform float size = 2.0; // @range(1, 4) @default(2)
+uniform float plain;
+uniform vec2 other; // @range(0, 1)
+float notuniform; // @range(0, 1)
+";
+        let params = parse_params(source);
+        assert_eq!(params, vec![
+            ParamSpec{
+                name: "speed".to_string(), min: 0.0, max: 10.0, default: 0.0,
+                keys: Some(("Q".to_string(), "A".to_string())),
+            },
+            ParamSpec{ name: "size".to_string(), min: 1.0, max: 4.0, default: 2.0, keys: None },
+        ]);
+    }
+
+    #[test]
+    fn adjust_clamps() {
+        let mut params = Params::default();
+        params.update_specs(parse_params("uniform float x; // @range(0, 10) @default(5)"));
+        params.adjust(0, 0.25);
+        assert_eq!(params.get("x"), Some(7.5));
+        params.adjust(0, 1.0);
+        assert_eq!(params.get("x"), Some(10.0));
+    }
+
+    #[test]
+    fn save_load() {
+        let mut params = Params::default();
+        params.update_specs(parse_params("uniform float x; // @range(0, 10)"));
+        params.adjust(0, 0.5);
+        let path = crate::test_dir("save_load").join("params.txt");
+        let path = path.to_str().unwrap();
+        params.save(path).unwrap();
+        let mut loaded = Params::default();
+        loaded.load(path).unwrap();
+        loaded.update_specs(parse_params("uniform float x; // @range(0, 10)"));
+        assert_eq!(loaded.values(), vec![("x".to_string(), 5.0)]);
+    }
+}
@@ -0,0 +1,867 @@
+use crate::shader::*;
+use crate::texture::*;
+use crate::params::*;
+use crate::FragConf;
+use crate::error::Error;
+use crate::audio::*;
+use crate::export::*;
+use std::sync::Arc;
+
+/// Per frame values of the built in uniforms
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Inputs{
+    pub time: f32,
+    pub delta_time: f32,
+    pub frame: u32,
+    pub frame_rate: f32,
+    pub mouse: [f32; 4],
+    /// year, month (0 based), day, seconds since midnight, in UTC
+    pub date: [f32; 4],
+}
+
+impl Inputs{
+    /// Date like iDate, from a time since the unix epoch
+    pub fn date(since_epoch: std::time::Duration) -> [f32; 4]{
+        let secs = since_epoch.as_secs();
+        let seconds = (secs % 86400) as f32 + since_epoch.subsec_nanos() as f32 / 1e9;
+        // days to civil date, from Howard Hinnant's date algorithms
+        let z = (secs / 86400) as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        [year as f32, (month - 1) as f32, day as f32, seconds]
+    }
+}
+
+/// Uniforms every render pass gets.
+/// Shadertoy passes have some with other types: iResolution is a vec3 and iFrame an int.
+struct Builtins{
+    shadertoy: bool,
+    sample_rate: u32,
+    time: Uniform,
+    delta_time: Uniform,
+    time_delta: Uniform,
+    frame: Uniform,
+    frame_rate: Uniform,
+    mouse: Uniform,
+    date: Uniform,
+    channel_time: Uniform,
+    aspect: Uniform,
+    resolution: Uniform,
+    window_resolution: Uniform,
+    pixel_size: Uniform,
+    tile: Uniform,
+    view: Uniform,
+    tonemap: Uniform,
+    exposure: Uniform,
+}
+
+impl Builtins{
+    fn new(program: &Program, size: Size, shadertoy: bool, sample_rate: u32) -> Self{
+        program.set_used();
+        let mut builtins = Self{
+            shadertoy,
+            sample_rate,
+            time: Uniform::new(program, "iTime"),
+            delta_time: Uniform::new(program, "iDeltaTime"),
+            time_delta: Uniform::new(program, "iTimeDelta"),
+            frame: Uniform::new(program, "iFrame"),
+            frame_rate: Uniform::new(program, "iFrameRate"),
+            mouse: Uniform::new(program, "iMouse"),
+            date: Uniform::new(program, "iDate"),
+            channel_time: Uniform::new(program, "iChannelTime"),
+            aspect: Uniform::new(program, "iAspect"),
+            resolution: Uniform::new(program, "iResolution"),
+            window_resolution: Uniform::new(program, "iWindowResolution"),
+            pixel_size: Uniform::new(program, "iPixelSize"),
+            tile: Uniform::new(program, "iTile"),
+            view: Uniform::new(program, "iView"),
+            tonemap: Uniform::new(program, "iTonemap"),
+            exposure: Uniform::new(program, "iExposure"),
+        };
+        builtins.reload(program, size);
+        builtins
+    }
+
+    // program must be in use
+    fn reload(&mut self, program: &Program, (cw, ch, ww, wh): Size){
+        for uniform in [
+            &mut self.time, &mut self.delta_time, &mut self.time_delta, &mut self.frame,
+            &mut self.frame_rate, &mut self.mouse, &mut self.date, &mut self.channel_time,
+            &mut self.aspect, &mut self.resolution, &mut self.window_resolution, &mut self.pixel_size,
+            &mut self.tile, &mut self.view, &mut self.tonemap, &mut self.exposure,
+        ]{
+            uniform.reload(program);
+        }
+        self.view.set_4f(0.0, 0.0, 1.0, 1.0);
+        if self.shadertoy{
+            // every channel is canvas sized or a texture, assume canvas sized
+            Uniform::new(program, "iChannelResolution")
+                .set_value(&UniformValue::Vec3Array(vec![[cw as f32, ch as f32, 1.0]; 4]));
+            Uniform::new(program, "iSampleRate").set_1f(self.sample_rate as f32);
+        }
+        self.set_view((0, 0), (cw, ch), (cw, ch), (ww, wh));
+        self.set(&Inputs::default());
+    }
+
+    // program must be in use, the canvas shows the part at the offset of an image of the given size
+    fn set_view(&self, (x, y): (i32, i32), (cw, ch): (i32, i32), (iw, ih): (i32, i32), (ww, wh): (i32, i32)){
+        self.aspect.set_1f(iw as f32 / ih as f32);
+        if self.shadertoy{
+            self.resolution.set_3f(iw as f32, ih as f32, 1.0);
+        } else {
+            self.resolution.set_2f(iw as f32, ih as f32);
+        }
+        self.window_resolution.set_2f(ww as f32, wh as f32);
+        self.pixel_size.set_2f(1.0 / iw as f32, 1.0 / ih as f32);
+        self.tile.set_4f(x as f32, y as f32, cw as f32, ch as f32);
+    }
+
+    fn set(&self, inputs: &Inputs){
+        let [mx, my, mz, mw] = inputs.mouse;
+        let [year, month, day, seconds] = inputs.date;
+        self.time.set_1f(inputs.time);
+        self.delta_time.set_1f(inputs.delta_time);
+        self.time_delta.set_1f(inputs.delta_time);
+        if self.shadertoy{
+            self.frame.set_1i(inputs.frame as i32);
+            self.channel_time.set_value(&UniformValue::FloatArray(vec![inputs.time; 4]));
+        } else {
+            self.frame.set_1ui(inputs.frame);
+        }
+        self.frame_rate.set_1f(inputs.frame_rate);
+        self.mouse.set_4f(mx, my, mz, mw);
+        self.date.set_4f(year, month, day, seconds);
+    }
+
+    // program must be in use, the part of the window the post pass draws
+    fn set_part(&self, (x, y): (i32, i32), (w, h): (i32, i32), (ww, wh): (i32, i32)){
+        let (ww, wh) = (ww as f32, wh as f32);
+        self.view.set_4f(x as f32 / ww, y as f32 / wh, w as f32 / ww, h as f32 / wh);
+    }
+
+    fn set_tonemap(&self, tonemap: Tonemap, exposure: f32){
+        self.tonemap.set_1i(tonemap.id());
+        self.exposure.set_1f(exposure);
+    }
+}
+
+// canvas width and height, window width and height
+type Size = (i32, i32, i32, i32);
+
+/// Uniforms besides the built ins that every pass gets:
+/// samplers with their texture unit and custom uniforms
+struct Bindings{
+    samplers: Vec<(String, i32)>,
+    custom: Vec<(String, UniformSource)>,
+    // a texture unit nothing is bound to, for Shadertoy channels without a buffer
+    empty_unit: i32,
+    // of the audio file, for iSampleRate
+    sample_rate: u32,
+}
+
+impl Bindings{
+    fn declarations(&self) -> String{
+        let mut declarations: String = self.samplers.iter()
+            .map(|(name, _)| format!("uniform sampler2D {};\n", name))
+            .collect();
+        for (name, source) in &self.custom{
+            declarations.push_str(&source.value(0.0).declaration(name));
+        }
+        declarations
+    }
+
+    // program must be in use, sets the samplers and returns the custom uniforms
+    fn resolve(&self, program: &Program) -> Vec<Uniform>{
+        for i in 0..4{
+            Uniform::new(program, &format!("iChannel{}", i)).set_1i(self.empty_unit);
+        }
+        for (name, unit) in &self.samplers{
+            Uniform::new(program, name).set_1i(*unit);
+        }
+        self.custom.iter().map(|(name, _)| Uniform::new(program, name)).collect()
+    }
+}
+
+fn set_custom(uniforms: &[Uniform], values: &[UniformValue]){
+    for (uniform, value) in uniforms.iter().zip(values){
+        uniform.set_value(value);
+    }
+}
+
+fn resolve_params(program: &Program, params: &Params) -> Vec<Uniform>{
+    params.specs.iter().map(|spec| Uniform::new(program, &spec.name)).collect()
+}
+
+fn set_params(uniforms: &[Uniform], values: &[(String, f32)]){
+    for (uniform, (_, value)) in uniforms.iter().zip(values){
+        uniform.set_1f(*value);
+    }
+}
+
+/// Renders one streamer into a pair of canvas sized textures, swapping them every frame.
+/// This way a pass can read its own previous frame.
+struct Pass{
+    streamer: ShaderStreamer,
+    program: Program,
+    builtins: Builtins,
+    custom: Vec<Uniform>,
+    params: Vec<Uniform>,
+    targets: [(gl::types::GLuint, gl::types::GLuint); 2],
+    current: usize,
+}
+
+impl Pass{
+    fn new(mut streamer: ShaderStreamer, bindings: &Bindings, size: Size, pixelate: bool, format: CanvasFormat)
+        -> Result<Self, Error>
+    {
+        let (cw, ch, _, _) = size;
+        streamer.declare(&bindings.declarations());
+        let program = init_program(&mut streamer)?;
+        let builtins = Builtins::new(&program, size, streamer.is_shadertoy(), bindings.sample_rate);
+        let custom = bindings.resolve(&program);
+        let targets = [
+            init_rendertarget(cw, ch, pixelate, format)?,
+            init_rendertarget(cw, ch, pixelate, format)?,
+        ];
+        Ok(Self{ streamer, program, builtins, custom, params: Vec::new(), targets, current: 0 })
+    }
+
+    fn rebuild(&mut self, bindings: &Bindings, size: Size) -> bool{
+        match self.streamer.build(false){
+            Ok(program) => {
+                self.program = program;
+                self.program.set_used();
+                self.builtins.reload(&self.program, size);
+                self.custom = bindings.resolve(&self.program);
+                true
+            },
+            Err(e) => {
+                println!("{}", e);
+                false
+            },
+        }
+    }
+
+    // texture with the latest output
+    fn output(&self) -> gl::types::GLuint{
+        self.targets[self.current].1
+    }
+}
+
+impl Drop for Pass{
+    fn drop(&mut self){
+        unsafe{
+            for (fbo, tex) in &self.targets{
+                gl::DeleteFramebuffers(1, fbo);
+                gl::DeleteTextures(1, tex);
+            }
+        }
+    }
+}
+
+/// Puts the canvas on screen, with the user's post streamer if there is one
+struct Post{
+    streamer: Option<ShaderStreamer>,
+    program: Program,
+    builtins: Builtins,
+    custom: Vec<Uniform>,
+    params: Vec<Uniform>,
+}
+
+impl Post{
+    fn new(streamer: Option<ShaderStreamer>, bindings: &Bindings, canvas_unit: i32, size: Size, version: &str)
+        -> Result<Self, Error>
+    {
+        let (streamer, program) = match streamer{
+            Some(mut streamer) => {
+                streamer.declare(&bindings.declarations());
+                let program = match streamer.build(true){
+                    Ok(program) => program,
+                    Err(e) => {
+                        println!("{}", e);
+                        streamer.fallback().build(true)?
+                    },
+                };
+                (Some(streamer), program)
+            },
+            None => {
+                let mut default = ShaderStreamer::new().into_post();
+                default.set_glsl_version(version);
+                (None, default.fallback().build(true)?)
+            },
+        };
+        let builtins = Builtins::new(&program, size, false, bindings.sample_rate);
+        let custom = bindings.resolve(&program);
+        Uniform::new(&program, "iCanvas").set_1i(canvas_unit);
+        Ok(Self{ streamer, program, builtins, custom, params: Vec::new() })
+    }
+
+    fn rebuild(&mut self, bindings: &Bindings, canvas_unit: i32, size: Size) -> bool{
+        let streamer = match &mut self.streamer{
+            Some(streamer) if streamer.is_dirty() => streamer,
+            _ => return false,
+        };
+        println!("Frag: rebuilding post shader.");
+        match streamer.build(false){
+            Ok(program) => {
+                self.program = program;
+                self.program.set_used();
+                self.builtins.reload(&self.program, size);
+                self.custom = bindings.resolve(&self.program);
+                Uniform::new(&self.program, "iCanvas").set_1i(canvas_unit);
+                true
+            },
+            Err(e) => {
+                println!("{}", e);
+                false
+            },
+        }
+    }
+}
+
+/// All passes, the image pass renders last into the canvas, the post pass puts it on screen.
+/// Texture units: first the buffers, then the textures, the post pass has the canvas last.
+pub(crate) struct Pipeline{
+    buffer_count: usize,
+    bindings: Bindings,
+    params: Params,
+    textures: Vec<Texture>,
+    passes: Vec<Pass>,
+    post: Post,
+    vao: gl::types::GLuint,
+    size: Size,
+    version: String,
+    // compiled when first drawn
+    timeline: Option<(Program, Uniform)>,
+    audio: Option<AudioTexture>,
+    pixelate: bool,
+    format: CanvasFormat,
+    tonemap: Tonemap,
+    exposure: f32,
+    // window sized target in the canvas format, for exporting the post pass without clamping or losing alpha
+    output: Option<(gl::types::GLuint, gl::types::GLuint)>,
+}
+
+// height of the timeline bar in window pixels
+const TIMELINE_HEIGHT: i32 = 6;
+
+impl Pipeline{
+    /// Takes the buffers, textures and post streamer out of the conf, image is the main streamer
+    pub fn new(conf: &mut FragConf, mut image: ShaderStreamer) -> Result<Self, Error>{
+        let size = (conf.cw, conf.ch, conf.ww, conf.wh);
+        let version = conf.glsl_version();
+        let buffers = std::mem::take(&mut conf.buffers);
+        let buffer_count = buffers.len();
+        let mut samplers = Vec::new();
+        for (i, (name, _)) in buffers.iter().enumerate(){
+            samplers.push((format!("iChannel{}", i), i as i32));
+            samplers.push((name.clone(), i as i32));
+        }
+        let mut textures = Vec::new();
+        for (name, texture_conf) in std::mem::take(&mut conf.textures){
+            samplers.push((name, (buffer_count + textures.len()) as i32));
+            textures.push(Texture::new(texture_conf)?);
+        }
+        let empty_unit = (buffer_count + textures.len()) as i32 + 1;
+        let audio = match &conf.audio{
+            Some(path) => {
+                let audio = Arc::new(Audio::load(path)?);
+                samplers.push(("iAudio".to_string(), empty_unit + 1));
+                for (i, name) in ["iBass", "iMid", "iTreble"].iter().enumerate(){
+                    let audio = audio.clone();
+                    conf.uniforms.push((name.to_string(), UniformSource::Fn(Box::new(
+                        move |t| UniformValue::Float(audio.bands(t)[i])
+                    ))));
+                }
+                let beat = audio.clone();
+                conf.uniforms.push(("iBeat".to_string(), UniformSource::Fn(Box::new(
+                    move |t| UniformValue::Float(beat.beat(t))
+                ))));
+                Some(AudioTexture::new(audio))
+            },
+            None => None,
+        };
+        let sample_rate = audio.as_ref().map_or(DEFAULT_SAMPLE_RATE, |audio| audio.sample_rate());
+        let bindings = Bindings{ samplers, custom: std::mem::take(&mut conf.uniforms), empty_unit, sample_rate };
+        let mut passes = Vec::new();
+        for (_, mut streamer) in buffers{
+            streamer.set_glsl_version(&version);
+            passes.push(Pass::new(streamer, &bindings, size, conf.pixelate, conf.canvas_format)?);
+        }
+        image.set_glsl_version(&version);
+        passes.push(Pass::new(image, &bindings, size, conf.pixelate, conf.canvas_format)?);
+        let canvas_unit = (buffer_count + textures.len()) as i32;
+        let mut post_streamer = conf.post_streamer.take();
+        if let Some(streamer) = &mut post_streamer{
+            streamer.set_glsl_version(&version);
+        }
+        let post = Post::new(post_streamer, &bindings, canvas_unit, size, &version)?;
+        let vao = init_quad();
+        let mut params = Params::default();
+        if let Some(path) = &conf.params_file{
+            if std::path::Path::new(path).exists(){
+                params.load(path)?;
+            }
+        }
+        let mut pipeline = Self{
+            buffer_count, bindings, params, textures, passes, post, vao, size, version, timeline: None, audio,
+            pixelate: conf.pixelate, format: conf.canvas_format, tonemap: conf.tonemap, exposure: conf.exposure, output: None,
+        };
+        pipeline.update_params();
+        Ok(pipeline)
+    }
+
+    // collect the param specs of all passes and resolve them in every program
+    fn update_params(&mut self){
+        let mut specs: Vec<ParamSpec> = Vec::new();
+        let streamers = self.passes.iter().map(|pass| &pass.streamer)
+            .chain(self.post.streamer.as_ref());
+        for streamer in streamers{
+            for spec in streamer.params(){
+                if !specs.iter().any(|s| s.name == spec.name){
+                    specs.push(spec.clone());
+                }
+            }
+        }
+        self.params.update_specs(specs);
+        for pass in &mut self.passes{
+            pass.params = resolve_params(&pass.program, &self.params);
+        }
+        self.post.params = resolve_params(&self.post.program, &self.params);
+    }
+
+    /// Tunable params annotated in the shaders
+    pub fn params(&mut self) -> &mut Params{
+        &mut self.params
+    }
+
+    /// Start watching the files of all passes and textures
+    pub fn start(&mut self) -> Result<(), Error>{
+        for pass in &mut self.passes{
+            pass.streamer.start()?;
+        }
+        if let Some(streamer) = &mut self.post.streamer{
+            streamer.start()?;
+        }
+        let image = self.passes.last_mut().expect("Frag: pipeline always has an image pass.");
+        for texture in &self.textures{
+            image.streamer.watch(texture.path(), texture.dirty_flag())?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild passes and reload textures with changed files, returns true if anything changed
+    pub fn rebuild(&mut self) -> bool{
+        let mut rebuild = false;
+        for pass in &mut self.passes{
+            if pass.streamer.is_dirty(){
+                println!("Frag: rebuilding shader.");
+                rebuild |= pass.rebuild(&self.bindings, self.size);
+            }
+        }
+        for texture in &self.textures{
+            rebuild |= texture.reload();
+        }
+        rebuild |= self.post.rebuild(&self.bindings, self.canvas_unit() as i32, self.size);
+        if rebuild{
+            self.update_params();
+        }
+        rebuild
+    }
+
+    /// True if the canvas can't be used as is, because of scaling, post processing or tonemapping
+    pub fn needs_post(&self) -> bool{
+        let (cw, ch, ww, wh) = self.size;
+        self.post.streamer.is_some() || cw != ww || ch != wh
+            || self.tonemap != Tonemap::None || self.exposure != 1.0 || self.format == CanvasFormat::R32F
+    }
+
+    pub fn canvas_format(&self) -> CanvasFormat{
+        self.format
+    }
+
+    /// Window sized framebuffer in the canvas format, to draw to instead of the screen when
+    /// exporting, so float canvases are not clamped to 8 bits and alpha is kept
+    pub fn output_fbo(&mut self) -> Result<gl::types::GLuint, Error>{
+        if self.output.is_none(){
+            let (_, _, ww, wh) = self.size;
+            self.output = Some(init_rendertarget(ww, wh, false, self.format)?);
+        }
+        Ok(self.output.expect("created above").0)
+    }
+
+    /// Render all passes into their targets.
+    /// If a screen framebuffer is given, the canvas is put on it by the post pass.
+    /// Otherwise the canvas framebuffer is left bound.
+    pub fn draw(&mut self, inputs: &Inputs, screen_fbo: Option<gl::types::GLuint>){
+        let (cw, ch, _, _) = self.size;
+        let custom = self.custom_values(inputs);
+        let params = self.params.values();
+        unsafe{
+            gl::BindVertexArray(self.vao);
+            gl::Viewport(0, 0, cw, ch);
+            for (i, texture) in self.textures.iter().enumerate(){
+                gl::ActiveTexture(gl::TEXTURE0 + (self.buffer_count + i) as u32);
+                gl::BindTexture(gl::TEXTURE_2D, texture.id());
+            }
+            if let Some(audio) = &self.audio{
+                gl::ActiveTexture(gl::TEXTURE0 + self.bindings.empty_unit as u32 + 1);
+                audio.update(inputs.time);
+            }
+            for i in 0..self.passes.len(){
+                // buffers that already rendered this frame give their new output,
+                // the others and the pass itself their output from the previous frame
+                self.bind_buffers();
+                let pass = &mut self.passes[i];
+                let next = 1 - pass.current;
+                gl::BindFramebuffer(gl::FRAMEBUFFER, pass.targets[next].0);
+                pass.program.set_used();
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+                pass.builtins.set(inputs);
+                set_custom(&pass.custom, &custom);
+                set_params(&pass.params, &params);
+                gl::DrawArrays(gl::TRIANGLES, 0, 6);
+                pass.current = next;
+            }
+            if let Some(screen_fbo) = screen_fbo{
+                self.draw_post(inputs, &custom, &params, self.canvas_tex(), screen_fbo);
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    /// Render the last drawn frame again, from the same previous frame, so feedback buffers
+    /// don't advance. For when only the params, mouse or shaders changed.
+    pub fn redraw(&mut self, inputs: &Inputs, screen_fbo: Option<gl::types::GLuint>){
+        for pass in &mut self.passes{
+            pass.current = 1 - pass.current;
+        }
+        self.draw(inputs, screen_fbo);
+    }
+
+    /// Clear every pass to black like at the start, for feedback buffers
+    pub fn reset(&mut self){
+        for pass in &self.passes{
+            for (fbo, _) in &pass.targets{
+                unsafe{
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, *fbo);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                }
+            }
+        }
+    }
+
+    /// Put the last drawn canvas on another framebuffer with the post pass, without rendering
+    /// the passes again, like for saving the frame on screen to the output framebuffer
+    pub fn redraw_post(&self, inputs: &Inputs, fbo: gl::types::GLuint){
+        let custom = self.custom_values(inputs);
+        let params = self.params.values();
+        unsafe{
+            gl::BindVertexArray(self.vao);
+            self.draw_post(inputs, &custom, &params, self.canvas_tex(), fbo);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    fn custom_values(&self, inputs: &Inputs) -> Vec<UniformValue>{
+        self.bindings.custom.iter().map(|(_, source)| source.value(inputs.time)).collect()
+    }
+
+    // vao must be bound
+    fn draw_post(&self, inputs: &Inputs, custom: &[UniformValue], params: &[(String, f32)],
+        canvas: gl::types::GLuint, fbo: gl::types::GLuint)
+    {
+        let (_, _, ww, wh) = self.size;
+        self.bind_buffers();
+        unsafe{
+            gl::ActiveTexture(gl::TEXTURE0 + self.canvas_unit() as u32);
+            gl::BindTexture(gl::TEXTURE_2D, canvas);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            self.post.program.set_used();
+            self.post.builtins.set(inputs);
+            self.post.builtins.set_tonemap(self.tonemap, self.exposure);
+            set_custom(&self.post.custom, custom);
+            set_params(&self.post.params, params);
+            gl::Viewport(0, 0, ww, wh);
+            gl::DrawArrays(gl::TRIANGLES, 0, 6);
+        }
+    }
+
+    /// Render one frame as an image of any size, in tiles of the canvas size.
+    /// It looks like the screen at that size: the passes render at the canvas resolution relative
+    /// to the window, and the post pass scales that to the image, pixelated if the canvas is.
+    /// Passes see that resolution as iResolution and its pixel as fragCoord. When the canvas is the
+    /// size of the window the post pass is applied to every tile. Buffers are rendered per tile too,
+    /// so sampling them at other pixels does not work across tiles, and feedback buffers see the
+    /// frame on screen as the previous frame in every tile.
+    /// Supersampling renders the image that many times bigger in both directions,
+    /// and averages the pixels back down. Returns the pixels, bottom row first.
+    pub fn render_image(&mut self, inputs: &Inputs, (w, h): (i32, i32), supersample: u32, float: bool)
+        -> Result<Pixels, Error>
+    {
+        let size = self.size;
+        let (cw, ch, ww, wh) = size;
+        let scale = supersample.max(1) as i32;
+        let (iw, ih) = (w * scale, h * scale);
+        let (sw, sh) = ((iw * cw / ww).max(1), (ih * ch / wh).max(1));
+        // the whole canvas for the post pass to scale, when it isn't the image size
+        let scene = if (sw, sh) != (iw, ih){
+            Some(init_rendertarget(sw, sh, self.pixelate, self.format)?)
+        } else {
+            None
+        };
+        let (fbo, tex) = init_rendertarget(cw, ch, false, self.format)?;
+        let weight = 1.0 / (scale * scale) as f32;
+        let mut image = vec![0.0f32; (w * h) as usize * 4];
+        let mut accumulate = |offset: (i32, i32)|{
+            let pixels = read_pixels(cw, ch, float).to_f32();
+            for y in 0..ch.min(ih - offset.1){
+                for x in 0..cw.min(iw - offset.0){
+                    let (ix, iy) = ((offset.0 + x) / scale, (offset.1 + y) / scale);
+                    let from = ((y * cw + x) * 4) as usize;
+                    let to = ((iy * w + ix) * 4) as usize;
+                    for c in 0..4{
+                        image[to + c] += pixels[from + c] * weight;
+                    }
+                }
+            }
+        };
+        // every tile starts from the frame on screen, which stays the current one
+        let currents = self.passes.iter().map(|pass| pass.current).collect::<Vec<_>>();
+        self.size = (cw, ch, cw, ch);
+        for offset in tiles((sw, sh), (cw, ch)){
+            self.set_view(offset, (cw, ch), (sw, sh), (iw, ih));
+            match scene{
+                Some((scene_fbo, _)) => {
+                    self.draw(inputs, None);
+                    unsafe{
+                        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, scene_fbo);
+                        gl::BlitFramebuffer(
+                            0, 0, cw, ch, offset.0, offset.1, offset.0 + cw, offset.1 + ch,
+                            gl::COLOR_BUFFER_BIT, gl::NEAREST
+                        );
+                    }
+                },
+                None => {
+                    self.draw(inputs, Some(fbo));
+                    accumulate(offset);
+                },
+            }
+            for (pass, current) in self.passes.iter_mut().zip(&currents){
+                pass.current = *current;
+            }
+        }
+        if let Some((scene_fbo, scene_tex)) = scene{
+            let custom = self.custom_values(inputs);
+            let params = self.params.values();
+            self.post.program.set_used();
+            self.post.builtins.set_view((0, 0), (sw, sh), (sw, sh), (iw, ih));
+            for offset in tiles((iw, ih), (cw, ch)){
+                self.post.program.set_used();
+                self.post.builtins.set_part(offset, (cw, ch), (iw, ih));
+                unsafe{
+                    gl::BindVertexArray(self.vao);
+                    self.draw_post(inputs, &custom, &params, scene_tex, fbo);
+                    gl::ActiveTexture(gl::TEXTURE0);
+                }
+                accumulate(offset);
+            }
+            self.post.builtins.set_part((0, 0), (1, 1), (1, 1));
+            unsafe{
+                gl::DeleteFramebuffers(1, &scene_fbo);
+                gl::DeleteTextures(1, &scene_tex);
+            }
+        }
+        self.size = size;
+        self.set_view((0, 0), (cw, ch), (cw, ch), (ww, wh));
+        unsafe{
+            gl::DeleteFramebuffers(1, &fbo);
+            gl::DeleteTextures(1, &tex);
+        }
+        Ok(Pixels::F32(image))
+    }
+
+    // the sizes every pass and the post pass see
+    fn set_view(&self, offset: (i32, i32), canvas: (i32, i32), image: (i32, i32), window: (i32, i32)){
+        let programs = self.passes.iter().map(|pass| (&pass.program, &pass.builtins))
+            .chain(std::iter::once((&self.post.program, &self.post.builtins)));
+        for (program, builtins) in programs{
+            program.set_used();
+            builtins.set_view(offset, canvas, image, window);
+        }
+    }
+
+    /// Draw the timeline bar over the bottom of the screen framebuffer, after `draw`.
+    /// Shows the time and loop region on a bar of whole 10 seconds.
+    pub fn draw_timeline(&mut self, time: f32, loop_region: Option<(f32, f32)>, screen_fbo: gl::types::GLuint)
+        -> Result<(), Error>
+    {
+        if self.timeline.is_none(){
+            let vert = format!("{}{}", version_header(&self.version), POST_VERT_SRC);
+            let frag = format!("{}{}", version_header(&self.version), TIMELINE_FRAG_SRC);
+            let program = Program::new(&vert, &frag, &["frag-internal"])?;
+            program.set_used();
+            let (_, _, ww, wh) = self.size;
+            Uniform::new(&program, "iWindowResolution").set_2f(ww as f32, wh as f32);
+            Uniform::new(&program, "iView").set_4f(0.0, 0.0, 1.0, 1.0);
+            let uniform = Uniform::new(&program, "iTimeline");
+            self.timeline = Some((program, uniform));
+        }
+        let (program, uniform) = self.timeline.as_ref().expect("timeline was just compiled");
+        let end = loop_region.map_or(time, |(_, end)| end.max(time));
+        let span = ((end / 10.0).floor() + 1.0).max(1.0) * 10.0;
+        let (start, end) = loop_region.unwrap_or((-1.0, -1.0));
+        let (_, _, ww, _) = self.size;
+        unsafe{
+            gl::BindVertexArray(self.vao);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, screen_fbo);
+            program.set_used();
+            uniform.set_4f(time, start, end, span);
+            gl::Viewport(0, 0, ww, TIMELINE_HEIGHT);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            gl::Disable(gl::BLEND);
+        }
+        Ok(())
+    }
+
+    fn bind_buffers(&self){
+        for (unit, buffer) in self.passes.iter().take(self.buffer_count).enumerate(){
+            unsafe{
+                gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+                gl::BindTexture(gl::TEXTURE_2D, buffer.output());
+            }
+        }
+    }
+
+    fn canvas_unit(&self) -> usize{
+        self.buffer_count + self.textures.len()
+    }
+
+    fn canvas_tex(&self) -> gl::types::GLuint{
+        self.passes.last().expect("Frag: pipeline always has an image pass.").output()
+    }
+}
+
+impl Drop for Pipeline{
+    fn drop(&mut self){
+        unsafe{
+            gl::DeleteVertexArrays(1, &self.vao);
+            if let Some((fbo, tex)) = &self.output{
+                gl::DeleteFramebuffers(1, fbo);
+                gl::DeleteTextures(1, tex);
+            }
+        }
+    }
+}
+
+// offsets of the tiles covering an image, row by row from the bottom
+fn tiles((w, h): (i32, i32), (tw, th): (i32, i32)) -> impl Iterator<Item = (i32, i32)>{
+    (0..(h + th - 1) / th).flat_map(move |ty| (0..(w + tw - 1) / tw).map(move |tx| (tx * tw, ty * th)))
+}
+
+fn init_program(streamer: &mut ShaderStreamer) -> Result<Program, Error>{
+    match streamer.build(true){
+        Ok(program) => Ok(program),
+        Err(e) => {
+            println!("{}", e);
+            streamer.fallback().build(true)
+        },
+    }
+}
+
+fn init_quad() -> gl::types::GLuint{
+    let vertices: Vec<f32> = vec![
+        -1., -1., 0., -1., 1., 0., 1., 1., 0., -1., -1., 0., 1., 1., 0., 1., -1., 0.
+    ];
+    let mut vbo: gl::types::GLuint = 0;
+    let mut vao: gl::types::GLuint = 0;
+
+    unsafe {
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER, // target
+            // size of data in bytes
+            (vertices.len() * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
+            vertices.as_ptr() as *const gl::types::GLvoid, // pointer to data
+            gl::STATIC_DRAW // usage
+        );
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(
+            0, 3,
+            gl::FLOAT, gl::FALSE,
+            (3 * std::mem::size_of::<f32>()) as gl::types::GLint, std::ptr::null()
+        );
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+    }
+    vao
+}
+
+fn init_rendertarget(cw: i32, ch: i32, pixelate: bool, format: CanvasFormat)
+    -> Result<(gl::types::GLuint, gl::types::GLuint), Error>
+{
+    let mut canvas_fbo: gl::types::GLuint = 0;
+    let mut canvas_tex: gl::types::GLuint = 0;
+
+    unsafe{
+        gl::GenFramebuffers(1, &mut canvas_fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, canvas_fbo);
+        gl::GenTextures(1, &mut canvas_tex);
+        gl::BindTexture(gl::TEXTURE_2D, canvas_tex);
+
+        let (internal, pixel_format, pixel_type) = format.gl();
+        gl::TexImage2D(
+            gl::TEXTURE_2D, 0, internal as i32, cw, ch, 0,
+            pixel_format, pixel_type, std::ptr::null()
+        );
+        if format == CanvasFormat::R32F{
+            // sample as gray
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_G, gl::RED as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_B, gl::RED as i32);
+        }
+        let filter = if pixelate { gl::NEAREST } else { gl::LINEAR } as i32;
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, canvas_tex, 0
+        );
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE{
+            gl::DeleteFramebuffers(1, &canvas_fbo);
+            gl::DeleteTextures(1, &canvas_tex);
+            return Err(Error::Gl(format!("could not initialize canvas framebuffer with format {:?}.", format)));
+        }
+        // start out black, feedback passes read this before their first frame
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+    }
+
+    Ok((canvas_fbo, canvas_tex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn date() {
+        assert_eq!(Inputs::date(Duration::ZERO), [1970.0, 0.0, 1.0, 0.0]);
+        // 2024-02-29 12:30:15.5 UTC
+        assert_eq!(Inputs::date(Duration::from_millis(1_709_209_815_500)), [2024.0, 1.0, 29.0, 45015.5]);
+        // 2000-12-31 23:59:59 UTC
+        assert_eq!(Inputs::date(Duration::from_secs(978_307_199)), [2000.0, 11.0, 31.0, 86399.0]);
+    }
+}
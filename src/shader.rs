@@ -1,33 +1,60 @@
 use std::ffi::{ CStr, CString };
 use std::fs::File;
 use std::io::Read;
-use std::sync::atomic::AtomicU32;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
-use hotwatch::{ Hotwatch, Event };
+use crate::params::*;
+use crate::error::Error;
+use crate::diagnostic::*;
+use crate::include::*;
+use crate::watch::Watcher;
 
-static STREAM_FLAG: AtomicU32 = AtomicU32::new(0);
+// the sources and headers below get a #version line from `version_header` in front of them
 
 pub const RENDER_VERT_SRC: &str = "
-#version 450 core
 layout (location = 0) in vec3 Position;
 uniform float iAspect;
+uniform vec2 iResolution;
+// offset and size of the rendered part of the image, for tiled stills
+uniform vec4 iTile;
 
 out vec2 uv;
+out vec2 fragCoord;
 
 void main()
 {
-    uv = Position.xy * 0.5;
-    uv.x *= iAspect;
+    fragCoord = iTile.xy + (Position.xy * 0.5 + vec2(0.5)) * iTile.zw;
+    uv = (fragCoord / iResolution - vec2(0.5)) * vec2(iAspect, 1.0);
     gl_Position = vec4(Position, 1.0);
 }
 ";
 
+/// Built in inputs of a render pass:
+/// - `uv`: centered coordinates, y from -0.5 to 0.5 and x scaled by the aspect ratio
+/// - `fragCoord`: pixel coordinates on the canvas, the same as `gl_FragCoord.xy`
+///   except for tiled stills, where it is the pixel of the whole image
+/// - `iTime`, `iDeltaTime`: time and time since the previous frame, in seconds
+/// - `iFrame`: number of the frame, starting at 0
+/// - `iFrameRate`: frames per second, the video framerate when rendering
+/// - `iDate`: year, month (0 based), day and seconds since midnight, in UTC
+/// - `iAspect`, `iResolution`: aspect ratio and size of the canvas in pixels
+/// - `iWindowResolution`: size of the window or video in pixels
+/// - `iPixelSize`: size of a canvas pixel in texture coordinates, `1.0 / iResolution`
+/// - `iMouse`: mouse like Shadertoy has it, in canvas pixels
 pub const RENDER_FRAG_HEADER: &str = "
-#version 450 core
 in vec2 uv;
+in vec2 fragCoord;
 uniform float iTime;
+uniform float iDeltaTime;
+uniform uint iFrame;
+uniform float iFrameRate;
+uniform vec4 iDate;
 uniform float iAspect;
 uniform vec2 iResolution;
+uniform vec2 iWindowResolution;
+uniform vec2 iPixelSize;
+uniform vec4 iMouse;
 
 out vec4 color;
 ";
@@ -40,32 +67,167 @@ void main()
 }
 ";
 
+pub const SHADERTOY_VERT_SRC: &str = "
+layout (location = 0) in vec3 Position;
+
+void main()
+{
+    gl_Position = vec4(Position, 1.0);
+}
+";
+
+/// Uniforms like Shadertoy has them, user code defines mainImage
+pub const SHADERTOY_FRAG_HEADER: &str = "
+uniform vec3 iResolution;
+uniform float iTime;
+uniform float iTimeDelta;
+uniform float iFrameRate;
+uniform int iFrame;
+uniform float iChannelTime[4];
+uniform vec3 iChannelResolution[4];
+uniform vec4 iMouse;
+uniform vec4 iDate;
+uniform float iSampleRate;
+uniform sampler2D iChannel0;
+uniform sampler2D iChannel1;
+uniform sampler2D iChannel2;
+uniform sampler2D iChannel3;
+// offset of the rendered part of the image, for tiled stills
+uniform vec4 iTile;
+
+out vec4 frag_FragColor;
+";
+
+pub const SHADERTOY_FRAG_STD_BODY: &str = "
+void mainImage(out vec4 fragColor, in vec2 fragCoord)
+{
+    vec2 uv = (fragCoord - 0.5 * iResolution.xy) / iResolution.y;
+    float rad = 0.4 + (sin(iTime) * 0.5 + 0.5) * 0.1;
+    fragColor = vec4(1.0f, 0.5f, 0.2f, 1.0f) * smoothstep(rad, rad-0.001, length(uv));
+}
+";
+
+/// Calls the Shadertoy entry point
+pub const SHADERTOY_FRAG_FOOTER: &str = "
+void main()
+{
+    vec4 fragColor = vec4(0.0, 0.0, 0.0, 1.0);
+    mainImage(fragColor, gl_FragCoord.xy + iTile.xy);
+    frag_FragColor = fragColor;
+}
+";
+
 pub const POST_VERT_SRC: &str = "
-#version 450 core
 layout (location = 0) in vec3 Position;
 
+uniform vec2 iResolution;
+uniform vec4 iTile;
+// part of the window drawn, from 0 to 1, only less than all of it for tiled stills
+uniform vec4 iView;
+
 out vec2 uv;
+out vec2 fragCoord;
 
 void main()
 {
-    uv = Position.xy * 0.5 + vec2(0.5);
+    uv = iView.xy + (Position.xy * 0.5 + vec2(0.5)) * iView.zw;
+    fragCoord = iTile.xy + uv * iTile.zw;
     gl_Position = vec4(Position, 1.0);
 }
 ";
 
-pub const POST_FRAG_SRC: &str = "
-#version 450 core
+/// Built in inputs of the post pass, like the render pass except:
+/// - `uv`: from 0 to 1, to sample `iCanvas` with
+/// - `fragCoord`: the canvas pixel under this window pixel
+/// - `tonemap(color)`: applies the exposure and tonemapping of the conf, the default post pass uses it
+pub const POST_FRAG_HEADER: &str = "
 in vec2 uv;
-uniform sampler2D tex;
+in vec2 fragCoord;
+uniform sampler2D iCanvas;
+uniform float iTime;
+uniform float iDeltaTime;
+uniform uint iFrame;
+uniform float iFrameRate;
+uniform vec4 iDate;
+uniform float iAspect;
+uniform vec2 iResolution;
+uniform vec2 iWindowResolution;
+uniform vec2 iPixelSize;
+uniform vec4 iMouse;
+uniform int iTonemap;
+uniform float iExposure;
 
 out vec4 color;
 
+// exposure and tonemapping of a HDR canvas, as configured
+vec4 tonemap(vec4 c)
+{
+    vec3 x = c.rgb * iExposure;
+    if (iTonemap == 1) {
+        x = x / (1.0 + x);
+    } else if (iTonemap == 2) {
+        x = clamp(x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+    }
+    return vec4(x, c.a);
+}
+";
+
+pub const POST_FRAG_STD_BODY: &str = "
 void main()
 {
-    color = texture(tex, uv);
+    color = tonemap(texture(iCanvas, uv));
 }
 ";
 
+/// Tonemapping of the post pass, from HDR canvas colors to the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemap{
+    /// Colors are clamped to [0, 1]
+    None,
+    /// c / (1 + c)
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl Tonemap{
+    // value of iTonemap
+    pub(crate) fn id(&self) -> i32{
+        *self as i32
+    }
+}
+
+/// Timeline bar in live mode, drawn at the bottom of the window over the post pass
+pub const TIMELINE_FRAG_SRC: &str = "
+in vec2 uv;
+uniform vec2 iWindowResolution;
+// time, loop start, loop end and end of the bar, in seconds
+uniform vec4 iTimeline;
+
+out vec4 color;
+
+void main()
+{
+    float t = uv.x * iTimeline.w;
+    float pixel = iTimeline.w / iWindowResolution.x;
+    color = vec4(0.0, 0.0, 0.0, 0.5);
+    if (t >= iTimeline.y && t <= iTimeline.z) color = vec4(0.3, 0.5, 1.0, 0.6);
+    if (abs(t - iTimeline.x) <= pixel) color = vec4(1.0);
+}
+";
+
+/// GLSL version used when nothing else is configured
+pub const DEFAULT_GLSL_VERSION: &str = "450 core";
+
+/// The #version line, GLSL ES also gets default precisions
+pub fn version_header(glsl_version: &str) -> String{
+    let mut header = format!("#version {}\n", glsl_version);
+    if glsl_version.ends_with("es"){
+        header.push_str("precision highp float;\nprecision highp int;\n");
+    }
+    header
+}
+
 #[derive(Debug)]
 enum StreamElement {
     Static(String),
@@ -74,18 +236,41 @@ enum StreamElement {
 
 #[derive(Debug)]
 pub struct ShaderStreamer{
+    vert: &'static str,
+    version: String,
+    header: String,
+    custom_header: Option<String>,
+    extra_header: String,
+    shadertoy: bool,
     segments: Vec<StreamElement>,
-    hotwatch: Hotwatch,
+    watcher: Option<Watcher>,
     stream_cache: Vec<String>,
+    // one flag per streamed file, set by the watcher when the file changes
+    stream_dirty: Vec<Arc<AtomicBool>>,
+    params: Vec<ParamSpec>,
+    include_paths: Vec<String>,
+    // included files that are watched, any change rebuilds everything
+    includes: Vec<String>,
+    includes_dirty: Arc<AtomicBool>,
 }
 
 impl ShaderStreamer{
     pub fn new() -> Self{
-        let hotwatch = Hotwatch::new().expect("hotwatch failed to initialize!");
         Self{
-            segments: vec![StreamElement::Static(RENDER_FRAG_HEADER.to_string())],
-            hotwatch,
+            vert: RENDER_VERT_SRC,
+            version: DEFAULT_GLSL_VERSION.to_string(),
+            header: RENDER_FRAG_HEADER.to_string(),
+            custom_header: None,
+            extra_header: String::new(),
+            shadertoy: false,
+            segments: Vec::new(),
+            watcher: None,
             stream_cache: Vec::new(),
+            stream_dirty: Vec::new(),
+            params: Vec::new(),
+            include_paths: Vec::new(),
+            includes: Vec::new(),
+            includes_dirty: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -93,6 +278,18 @@ impl ShaderStreamer{
         Self::new().with_str(RENDER_FRAG_STD_BODY)
     }
 
+    /// Streamer for code pasted from Shadertoy: it defines
+    /// `void mainImage(out vec4 fragColor, in vec2 fragCoord)` and gets the Shadertoy uniforms.
+    /// Buffers show up as iChannel0..3, in the order they are added.
+    pub fn shadertoy() -> Self{
+        Self{
+            vert: SHADERTOY_VERT_SRC,
+            header: SHADERTOY_FRAG_HEADER.to_string(),
+            shadertoy: true,
+            ..Self::new()
+        }
+    }
+
     pub fn with_str(mut self, string: &str) -> Self{
         self.segments.push(StreamElement::Static(string.to_string()));
         self
@@ -101,73 +298,177 @@ impl ShaderStreamer{
     pub fn with_file(mut self, file: &str) -> Self{
         self.segments.push(StreamElement::Streamed(file.to_string()));
         self.stream_cache.push(String::new());
+        self.stream_dirty.push(Arc::new(AtomicBool::new(false)));
+        self
+    }
+
+    /// Directory to search for `#include "file"` and `#include <file>` in streamed files.
+    /// Quoted includes are searched next to the including file first.
+    pub fn with_include_path(mut self, path: &str) -> Self{
+        self.include_paths.push(path.to_string());
+        self
+    }
+
+    /// Replace the generated header with the built in uniforms.
+    /// The #version line is still generated, see `FragConf::with_glsl_version`.
+    pub fn with_header(mut self, header: &str) -> Self{
+        self.custom_header = Some(header.to_string());
+        self
+    }
+
+    /// Add to the header, like extensions or defines
+    pub fn with_extra_header(mut self, header: &str) -> Self{
+        self.extra_header.push_str(header);
         self
     }
 
-    fn read_file(file: &str) -> Result<String, String>{
-        let mut file = match File::open(file){
-            Ok(f) => f,
-            Err(e) => { return Err(format!("{}", e)); }
+    // use as post pass, with the post header instead of the render header
+    pub(crate) fn into_post(mut self) -> Self{
+        self.vert = POST_VERT_SRC;
+        self.header = POST_FRAG_HEADER.to_string();
+        self.shadertoy = false;
+        self
+    }
+
+    pub(crate) fn is_shadertoy(&self) -> bool{
+        self.shadertoy
+    }
+
+    pub(crate) fn set_glsl_version(&mut self, version: &str){
+        self.version = version.to_string();
+    }
+
+    // the standard shader with the same version and kind of header, for when building fails
+    pub(crate) fn fallback(&self) -> Self{
+        let body = if self.vert == POST_VERT_SRC {
+            POST_FRAG_STD_BODY
+        } else if self.shadertoy {
+            SHADERTOY_FRAG_STD_BODY
+        } else {
+            RENDER_FRAG_STD_BODY
         };
+        Self{
+            vert: self.vert,
+            version: self.version.clone(),
+            header: self.header.clone(),
+            shadertoy: self.shadertoy,
+            ..Self::new()
+        }.with_str(body)
+    }
+
+    // declarations go right after the header, before any user code,
+    // without the ones the header already has
+    pub(crate) fn declare(&mut self, declarations: &str){
+        let header = self.custom_header.as_ref().unwrap_or(&self.header);
+        let declarations: String = declarations.lines()
+            .filter(|line| !header.lines().any(|h| h.trim() == line.trim()))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        self.segments.insert(0, StreamElement::Static(declarations));
+    }
+
+    fn read_file(file: &str) -> Result<String, Error>{
         let mut contents = String::new();
-        if let Err(e) = file.read_to_string(&mut contents){
-            return Err(format!("{}", e));
-        }
+        File::open(file)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| Error::io(file, e))?;
         Ok(contents)
     }
 
-    pub fn start(&mut self){
-        let mut stream_count = 0u32;
-        for element in &self.segments{
-            if let StreamElement::Streamed(file) = element{
-                self.hotwatch.watch(file, move |event: Event| {
-                    if let hotwatch::EventKind::Modify(_) = event.kind {
-                        println!("Frag: marked {:?} dirty.", event.paths);
-                        let flag = 1u32 << stream_count;
-                        STREAM_FLAG.fetch_or(flag, Ordering::SeqCst);
-                    }
-                }).expect("failed to watch file!");
-                stream_count += 1;
+    // the watcher is only created once something needs watching
+    fn watcher(&mut self) -> Result<&mut Watcher, Error>{
+        if self.watcher.is_none(){
+            self.watcher = Some(Watcher::new()?);
+        }
+        Ok(self.watcher.as_mut().expect("watcher was just created"))
+    }
+
+    /// Start watching the streamed files for changes
+    pub fn start(&mut self) -> Result<(), Error>{
+        let files = self.segments.iter().filter_map(|element| match element{
+            StreamElement::Streamed(file) => Some(file.clone()),
+            StreamElement::Static(_) => None,
+        }).collect::<Vec<_>>();
+        let flags = self.stream_dirty.clone();
+        let watcher = self.watcher()?;
+        for (file, flag) in files.iter().zip(flags){
+            watcher.watch(file, flag)?;
+        }
+        self.watch_includes(0)
+    }
+
+    // watch the included files from index on, if watching started
+    fn watch_includes(&mut self, from: usize) -> Result<(), Error>{
+        if let Some(watcher) = &mut self.watcher{
+            for file in &self.includes[from..]{
+                watcher.watch(file, self.includes_dirty.clone())?;
             }
         }
+        Ok(())
+    }
+
+    // watch another file with the same watcher, flag is set when it changes
+    pub(crate) fn watch(&mut self, file: &str, flag: Arc<AtomicBool>) -> Result<(), Error>{
+        self.watcher()?.watch(file, flag)
+    }
+
+    /// Annotated float uniforms found in the last successful build,
+    /// for example: `uniform float speed; // @range(0, 10) @default(2) @key(Q/A)`
+    pub fn params(&self) -> &[ParamSpec]{
+        &self.params
     }
 
     pub fn is_dirty(&self) -> bool{
-        STREAM_FLAG.load(Ordering::SeqCst) > 0
+        self.stream_dirty.iter().any(|dirty| dirty.load(Ordering::SeqCst))
+            || self.includes_dirty.load(Ordering::SeqCst)
     }
 
-    pub fn build(&mut self, all: bool) -> Result<Program, String>{
-        let flag = STREAM_FLAG.load(Ordering::Relaxed);
-        if self.stream_cache.len() > 31 {
-            return Err("Can only support up to 31 streamed files.".to_string());
+    pub fn build(&mut self, all: bool) -> Result<Program, Error>{
+        let mut stream_count = 0;
+        for element in &self.segments{
+            if let StreamElement::Streamed(file) = element{
+                let dirty = self.stream_dirty[stream_count].swap(false, Ordering::SeqCst);
+                if all || dirty{
+                    self.stream_cache[stream_count] = Self::read_file(file)?;
+                }
+                stream_count += 1;
+            }
         }
-        let mut concat = String::new();
-        let mut sections = Vec::new();
+        // includes are read again on every build
+        self.includes_dirty.store(false, Ordering::SeqCst);
+        let mut includer = Includer::new(&self.include_paths);
+        let version = version_header(&self.version);
+        let header = self.custom_header.as_ref().unwrap_or(&self.header);
         let mut stream_count = 0;
+        let mut sources = vec![
+            Source::new("frag-internal", &format!("{}{}{}", version, header, self.extra_header), 1)
+        ];
         for element in &self.segments{
             match element{
                 StreamElement::Streamed(file) => {
-                    let bit = 1u32 << stream_count;
-                    if all || (bit & flag) > 0{
-                        let content = Self::read_file(file)?;
-                        sections.push((content.chars().filter(|c| *c == '\n').count(), file.as_ref()));
-                        concat.push_str(&content);
-                        self.stream_cache[stream_count] = content;
-                        if !all {
-                            STREAM_FLAG.fetch_xor(bit, Ordering::SeqCst);
-                        }
-                    } else {
-                        concat.push_str(&self.stream_cache[stream_count]);
-                    }
+                    sources.extend(includer.expand(file, &self.stream_cache[stream_count])?);
                     stream_count += 1;
                 },
-                StreamElement::Static(string) => {
-                    concat.push_str(string);
-                    sections.push((string.chars().filter(|c| *c == '\n').count(), "frag-internal"));
-                },
+                StreamElement::Static(string) => sources.push(Source::new("frag-internal", string, 1)),
             }
         }
-        Program::new(RENDER_VERT_SRC, &concat, &sections)
+        let new_includes = includer.files.into_iter()
+            .filter(|file| !self.includes.contains(file))
+            .collect::<Vec<_>>();
+        let watched = self.includes.len();
+        self.includes.extend(new_includes);
+        self.watch_includes(watched)?;
+        if self.shadertoy{
+            sources.push(Source::new("frag-internal", SHADERTOY_FRAG_FOOTER, 1));
+        }
+        let concat = join_sources(&sources, line_stride());
+        let files = sources.iter().map(|source| source.file.as_str()).collect::<Vec<_>>();
+        let program = Program::new(&format!("{}{}", version, self.vert), &concat, &files)?;
+        for warning in program.warnings(){
+            println!("Frag: {}", warning);
+        }
+        self.params = parse_params(&concat);
+        Ok(program)
     }
 }
 
@@ -177,23 +478,56 @@ impl Default for ShaderStreamer{
     }
 }
 
-type Sections<'a,'b> = &'a[(usize, &'b str)];
+// file names by GLSL source string number
+type Sources<'a,'b> = &'a[&'b str];
+
+// Concatenate sources, every one after the first gets a `#line <line> <source>` directive
+// with its first line and its index as source string number, so the compiler reports lines per file.
+// The first one holds #version, nothing may come before it.
+fn join_sources(sources: &[Source], stride: usize) -> String{
+    let mut concat = String::new();
+    for (i, Source{ content, line, .. }) in sources.iter().enumerate(){
+        if i > 0{
+            concat.push_str(&format!("#line {} {}\n", i * stride + line, i));
+        }
+        concat.push_str(content);
+        if !content.is_empty() && !content.ends_with('\n'){
+            concat.push('\n');
+        }
+    }
+    concat
+}
+
+// Mesa reports every diagnostic in source string 0, whatever #line says. Deviating from
+// plain `#line <line> <source>`, the source is also encoded in the line number there,
+// which `parse_log` decodes. Other drivers get the real line numbers.
+fn line_stride() -> usize{
+    let version = unsafe{ gl::GetString(gl::VERSION) };
+    if version.is_null(){
+        return 0;
+    }
+    let version = unsafe{ CStr::from_ptr(version as *const _) };
+    if version.to_string_lossy().contains("Mesa"){ LINE_STRIDE } else { 0 }
+}
 
 pub struct Program {
     id: gl::types::GLuint,
+    warnings: Vec<Diagnostic>,
 }
 
 impl Program {
-    pub fn new(vert_source: &str, frag_source: &str, sections: Sections) -> Result<Self, String>{
-        let vert_cstr = if let Ok(cstr) = CString::new(vert_source){ cstr } else { return Err("Frag: could not make vert cstr.".to_string()); };
-        let frag_cstr = if let Ok(cstr) = CString::new(frag_source){ cstr } else { return Err("Frag: could not make frag cstr.".to_string()); };
+    pub fn new(vert_source: &str, frag_source: &str, files: Sources) -> Result<Self, Error>{
+        let vert_cstr = CString::new(vert_source)
+            .map_err(|_| Error::Gl("could not make vert cstr.".to_string()))?;
+        let frag_cstr = CString::new(frag_source)
+            .map_err(|_| Error::Gl("could not make frag cstr.".to_string()))?;
         let vert_shader = Shader::from_vert_source(&vert_cstr)?;
-        let frag_shader = Shader::from_frag_source(&frag_cstr, sections)?;
+        let frag_shader = Shader::from_frag_source(&frag_cstr, files)?;
 
-        Program::from_shaders(&[vert_shader, frag_shader], sections)
+        Program::from_shaders(&[vert_shader, frag_shader])
     }
 
-    pub fn from_shaders(shaders: &[Shader], sections: Sections) -> Result<Self, String> {
+    pub fn from_shaders(shaders: &[Shader]) -> Result<Self, Error> {
         let id = unsafe {
             let id = gl::CreateProgram();
             for shader in shaders {
@@ -207,20 +541,26 @@ impl Program {
                 gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut len);
                 let error = create_whitespace_cstring_with_len(len as usize);
                 gl::GetProgramInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
-                return Err(fix_error(error.to_string_lossy().into_owned(), sections));
+                return Err(Error::Link(error.to_string_lossy().trim().to_string()));
             }
             for shader in shaders {
                 gl::DetachShader(id, shader.id());
             }
             id
         };
-        Ok(Program { id })
+        let warnings = shaders.iter().flat_map(|shader| shader.warnings.iter().cloned()).collect();
+        Ok(Program { id, warnings })
     }
 
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
 
+    /// Warnings the compiler reported for the shaders of this program
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
     pub fn set_used(&self) {
         unsafe {
             gl::UseProgram(self.id);
@@ -238,25 +578,31 @@ impl Drop for Program {
 
 pub struct Shader {
     id: gl::types::GLuint,
+    warnings: Vec<Diagnostic>,
 }
 
 impl Shader {
-    pub fn from_source(source: &CStr, kind: gl::types::GLenum, sections: Sections) -> Result<Shader, String> {
-        let id = shader_from_source(source, kind, sections)?;
-        Ok(Shader { id })
+    pub fn from_source(source: &CStr, kind: gl::types::GLenum, files: Sources) -> Result<Shader, Error> {
+        let (id, warnings) = shader_from_source(source, kind, files)?;
+        Ok(Shader { id, warnings })
     }
 
-    pub fn from_vert_source(source: &CStr) -> Result<Shader, String> {
+    pub fn from_vert_source(source: &CStr) -> Result<Shader, Error> {
         Shader::from_source(source, gl::VERTEX_SHADER, &[])
     }
 
-    pub fn from_frag_source(source: &CStr, sections: Sections) -> Result<Shader, String> {
-        Shader::from_source(source, gl::FRAGMENT_SHADER, sections)
+    pub fn from_frag_source(source: &CStr, files: Sources) -> Result<Shader, Error> {
+        Shader::from_source(source, gl::FRAGMENT_SHADER, files)
     }
 
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
+
+    /// Warnings the compiler reported for this shader
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
 }
 
 impl Drop for Shader {
@@ -267,7 +613,9 @@ impl Drop for Shader {
     }
 }
 
-fn shader_from_source(source: &CStr, kind: gl::types::GLenum, sections: Sections) -> Result<gl::types::GLuint, String> {
+fn shader_from_source(source: &CStr, kind: gl::types::GLenum, files: Sources)
+    -> Result<(gl::types::GLuint, Vec<Diagnostic>), Error>
+{
     let id = unsafe { gl::CreateShader(kind) };
     unsafe {
         gl::ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
@@ -279,19 +627,29 @@ fn shader_from_source(source: &CStr, kind: gl::types::GLenum, sections: Sections
         gl::GetShaderiv(id, gl::COMPILE_STATUS, &mut success);
     }
 
-    if success == 0 {
-        let mut len: gl::types::GLint = 0;
+    // the log has warnings even when compiling succeeded
+    let mut len: gl::types::GLint = 0;
+    unsafe {
+        gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
+    }
+    let log = create_whitespace_cstring_with_len(len.max(0) as usize);
+    if len > 0 {
         unsafe {
-            gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
+            gl::GetShaderInfoLog(id, len, std::ptr::null_mut(), log.as_ptr() as *mut gl::types::GLchar);
         }
-        let error = create_whitespace_cstring_with_len(len as usize);
+    }
+    let log = log.to_string_lossy();
+    let log = log.trim_end_matches(['\0', ' ']);
+    let diagnostics = parse_log(log, files, success == 0);
+
+    if success == 0 {
         unsafe {
-            gl::GetShaderInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
+            gl::DeleteShader(id);
         }
-        return Err(fix_error(error.to_string_lossy().into_owned(), sections));
+        return Err(Error::Compile(diagnostics));
     }
 
-    Ok(id)
+    Ok((id, diagnostics))
 }
 
 fn create_whitespace_cstring_with_len(len: usize) -> CString {
@@ -303,52 +661,6 @@ fn create_whitespace_cstring_with_len(len: usize) -> CString {
     unsafe { CString::from_vec_unchecked(buffer) }
 }
 
-fn fix_error(string: String, sections: &[(usize, &str)]) -> String{
-    // Get line and file from error like this:
-    // 0(16) : error C1503: undefined variable "xcolor"
-    let parts: Vec<_> = string.split(' ').collect();
-    if sections.is_empty() {
-        return string;
-    }
-    if parts.len() < 3 {
-        return string;
-    }
-    if (parts[1], parts[2]) != (":", "error"){
-        return string;
-    }
-    let mut line_nr_str = String::new();
-    let mut collect = false;
-    for c in parts[0].chars(){
-        if c == '(' { collect = true; }
-        else if collect{
-            if !c.is_numeric() { break; }
-            else { line_nr_str.push(c); }
-        }
-    }
-    let line_nr = match line_nr_str.parse::<usize>(){
-        Ok(x) => x,
-        Err(_) => return string,
-    };
-    let mut start = 0;
-    let mut local_line_nr = 0;
-    let mut local_file = "";
-    let mut found = false;
-    for (lines, file) in sections{
-        if line_nr <= start + lines{
-            local_line_nr = line_nr - start;
-            local_file = file;
-            found = true;
-            break;
-        }
-        start += lines;
-    };
-    if !found {
-        return string;
-    }
-    format!("\n\tFile: {}\n\tLine:{}\n\tError: {}", local_file, local_line_nr,
-            parts.into_iter().skip(3).collect::<Vec<_>>().join(" "))
-}
-
 pub struct Uniform{
     loc: gl::types::GLint,
     cname: CString,
@@ -377,9 +689,214 @@ impl Uniform{
     pub fn set_1f(&self, v: f32){ unsafe{ gl::Uniform1f(self.loc, v); } }
     pub fn with_1f(self, v: f32) -> Self{ self.set_1f(v); self }
 
+    pub fn set_1i(&self, v: i32){ unsafe{ gl::Uniform1i(self.loc, v); } }
+    pub fn with_1i(self, v: i32) -> Self{ self.set_1i(v); self }
+
     pub fn set_1ui(&self, v: u32){ unsafe{ gl::Uniform1ui(self.loc, v); } }
     pub fn with_1ui(self, v: u32) -> Self{ self.set_1ui(v); self }
 
     pub fn set_2f(&self, x: f32, y: f32){ unsafe{ gl::Uniform2f(self.loc, x, y); } }
     pub fn with_2f(self, x: f32, y: f32) -> Self{ self.set_2f(x, y); self }
+
+    pub fn set_3f(&self, x: f32, y: f32, z: f32){ unsafe{ gl::Uniform3f(self.loc, x, y, z); } }
+    pub fn with_3f(self, x: f32, y: f32, z: f32) -> Self{ self.set_3f(x, y, z); self }
+
+    pub fn set_4f(&self, x: f32, y: f32, z: f32, w: f32){ unsafe{ gl::Uniform4f(self.loc, x, y, z, w); } }
+    pub fn with_4f(self, x: f32, y: f32, z: f32, w: f32) -> Self{ self.set_4f(x, y, z, w); self }
+
+    pub fn set_value(&self, value: &UniformValue){
+        unsafe{
+            match value{
+                UniformValue::Float(v) => gl::Uniform1f(self.loc, *v),
+                UniformValue::Int(v) => gl::Uniform1i(self.loc, *v),
+                UniformValue::UInt(v) => gl::Uniform1ui(self.loc, *v),
+                UniformValue::Bool(v) => gl::Uniform1i(self.loc, *v as i32),
+                UniformValue::Vec2(v) => gl::Uniform2fv(self.loc, 1, v.as_ptr()),
+                UniformValue::Vec3(v) => gl::Uniform3fv(self.loc, 1, v.as_ptr()),
+                UniformValue::Vec4(v) => gl::Uniform4fv(self.loc, 1, v.as_ptr()),
+                UniformValue::Mat3(v) => gl::UniformMatrix3fv(self.loc, 1, gl::FALSE, v.as_ptr()),
+                UniformValue::Mat4(v) => gl::UniformMatrix4fv(self.loc, 1, gl::FALSE, v.as_ptr()),
+                UniformValue::FloatArray(v) => gl::Uniform1fv(self.loc, v.len() as i32, v.as_ptr()),
+                UniformValue::IntArray(v) => gl::Uniform1iv(self.loc, v.len() as i32, v.as_ptr()),
+                UniformValue::Vec2Array(v) =>
+                    gl::Uniform2fv(self.loc, v.len() as i32, v.as_ptr() as *const f32),
+                UniformValue::Vec3Array(v) =>
+                    gl::Uniform3fv(self.loc, v.len() as i32, v.as_ptr() as *const f32),
+                UniformValue::Vec4Array(v) =>
+                    gl::Uniform4fv(self.loc, v.len() as i32, v.as_ptr() as *const f32),
+            }
+        }
+    }
+    pub fn with_value(self, value: &UniformValue) -> Self{ self.set_value(value); self }
+}
+
+/// Value of a custom uniform, the variant decides the GLSL type it is declared as
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformValue{
+    Float(f32),
+    Int(i32),
+    UInt(u32),
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    /// Column major
+    Mat3([f32; 9]),
+    /// Column major
+    Mat4([f32; 16]),
+    FloatArray(Vec<f32>),
+    IntArray(Vec<i32>),
+    Vec2Array(Vec<[f32; 2]>),
+    Vec3Array(Vec<[f32; 3]>),
+    Vec4Array(Vec<[f32; 4]>),
+}
+
+impl UniformValue{
+    /// GLSL declaration of a uniform with this value
+    pub fn declaration(&self, name: &str) -> String{
+        let (glsl_type, len) = match self{
+            Self::Float(_) => ("float", None),
+            Self::Int(_) => ("int", None),
+            Self::UInt(_) => ("uint", None),
+            Self::Bool(_) => ("bool", None),
+            Self::Vec2(_) => ("vec2", None),
+            Self::Vec3(_) => ("vec3", None),
+            Self::Vec4(_) => ("vec4", None),
+            Self::Mat3(_) => ("mat3", None),
+            Self::Mat4(_) => ("mat4", None),
+            Self::FloatArray(v) => ("float", Some(v.len())),
+            Self::IntArray(v) => ("int", Some(v.len())),
+            Self::Vec2Array(v) => ("vec2", Some(v.len())),
+            Self::Vec3Array(v) => ("vec3", Some(v.len())),
+            Self::Vec4Array(v) => ("vec4", Some(v.len())),
+        };
+        match len{
+            Some(len) => format!("uniform {} {}[{}];\n", glsl_type, name, len),
+            None => format!("uniform {} {};\n", glsl_type, name),
+        }
+    }
+}
+
+/// Lets you change a custom uniform from anywhere, for example another thread, while running
+#[derive(Debug, Clone)]
+pub struct UniformHandle{
+    value: Arc<Mutex<UniformValue>>,
+}
+
+impl UniformHandle{
+    pub fn new(value: UniformValue) -> Self{
+        Self{ value: Arc::new(Mutex::new(value)) }
+    }
+
+    /// Keep the same variant, the declaration in the shader does not change
+    pub fn set(&self, value: UniformValue){
+        *self.value.lock().expect("Frag: uniform handle poisoned.") = value;
+    }
+
+    pub fn get(&self) -> UniformValue{
+        self.value.lock().expect("Frag: uniform handle poisoned.").clone()
+    }
+}
+
+/// Where the value of a custom uniform comes from every frame
+pub enum UniformSource{
+    Const(UniformValue),
+    Handle(UniformHandle),
+    /// Function of iTime, deterministic when rendering to file
+    Fn(Box<dyn Fn(f32) -> UniformValue>),
+}
+
+impl UniformSource{
+    pub fn value(&self, t: f32) -> UniformValue{
+        match self{
+            Self::Const(value) => value.clone(),
+            Self::Handle(handle) => handle.get(),
+            Self::Fn(f) => f(t),
+        }
+    }
+}
+
+impl std::fmt::Debug for UniformSource{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        match self{
+            Self::Const(value) => f.debug_tuple("Const").field(value).finish(),
+            Self::Handle(handle) => f.debug_tuple("Handle").field(handle).finish(),
+            Self::Fn(_) => f.write_str("Fn"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_declarations() {
+        assert_eq!(UniformValue::Float(1.0).declaration("a"), "uniform float a;\n");
+        assert_eq!(UniformValue::Bool(true).declaration("b"), "uniform bool b;\n");
+        assert_eq!(UniformValue::Mat4([0.0; 16]).declaration("m"), "uniform mat4 m;\n");
+        assert_eq!(
+            UniformValue::Vec3Array(vec![[0.0; 3]; 4]).declaration("v"),
+            "uniform vec3 v[4];\n"
+        );
+    }
+
+    #[test]
+    fn uniform_sources() {
+        let handle = UniformHandle::new(UniformValue::Int(1));
+        let source = UniformSource::Handle(handle.clone());
+        handle.set(UniformValue::Int(2));
+        assert_eq!(source.value(0.0), UniformValue::Int(2));
+        let source = UniformSource::Fn(Box::new(|t| UniformValue::Float(t * 2.0)));
+        assert_eq!(source.value(1.5), UniformValue::Float(3.0));
+    }
+
+    #[test]
+    fn join_sources_lines() {
+        let sources = [
+            Source::new("frag-internal", "#version 450 core\n", 1),
+            Source::new("a.glsl", "float a;", 1),
+            Source::new("b.glsl", "", 1),
+            Source::new("c.glsl", "float c;\r\nfloat d;\r\n", 1),
+            Source::new("d.glsl", "float e;\n", 5),
+        ];
+        assert_eq!(join_sources(&sources, 0), "#version 450 core\n\
+            #line 1 1\nfloat a;\n\
+            #line 1 2\n\
+            #line 1 3\nfloat c;\r\nfloat d;\r\n\
+            #line 5 4\nfloat e;\n");
+        assert_eq!(join_sources(&sources, LINE_STRIDE), "#version 450 core\n\
+            #line 100001 1\nfloat a;\n\
+            #line 200001 2\n\
+            #line 300001 3\nfloat c;\r\nfloat d;\r\n\
+            #line 400005 4\nfloat e;\n");
+    }
+
+    #[test]
+    fn independent_dirty_state() {
+        let dir = crate::test_dir("independent_dirty_state");
+        let files = (0..40).map(|i| {
+            let path = dir.join(format!("{}.glsl", i));
+            std::fs::write(&path, "").unwrap();
+            path.to_string_lossy().into_owned()
+        }).collect::<Vec<_>>();
+        let mut a = files[..39].iter().fold(ShaderStreamer::new(), |s, f| s.with_file(f));
+        let mut b = ShaderStreamer::new().with_file(&files[39]);
+        a.start().unwrap();
+        b.start().unwrap();
+        std::fs::write(&files[39], "float x;").unwrap();
+        let start = std::time::Instant::now();
+        while !b.is_dirty() && start.elapsed().as_secs() < 5{
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(b.is_dirty());
+        assert!(!a.is_dirty());
+        std::fs::write(&files[38], "float y;").unwrap();
+        while !a.is_dirty() && start.elapsed().as_secs() < 10{
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(a.is_dirty());
+        assert!(a.stream_dirty[38].load(Ordering::SeqCst));
+        assert!(!a.stream_dirty[0].load(Ordering::SeqCst));
+    }
 }
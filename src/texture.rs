@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+use crate::error::Error;
+
+/// Texture filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter{
+    Nearest, Linear
+}
+
+/// Texture wrapping, what happens when sampling outside [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap{
+    Repeat, MirroredRepeat, ClampToEdge
+}
+
+/// Format of the canvas and buffers a pass renders into.
+/// Float formats are not clamped to [0, 1] and keep their precision in feedback loops.
+/// R32F only has a red channel, it is shown in gray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasFormat{
+    Rgba8, Rgba16F, Rgba32F, R32F
+}
+
+impl CanvasFormat{
+    pub(crate) fn is_float(&self) -> bool{
+        *self != Self::Rgba8
+    }
+
+    // internal format, format and type for glTexImage2D
+    pub(crate) fn gl(&self) -> (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum){
+        match self{
+            Self::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            Self::Rgba16F => (gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
+            Self::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+            Self::R32F => (gl::R32F, gl::RED, gl::FLOAT),
+        }
+    }
+}
+
+/// Config for an image that gets bound as `uniform sampler2D`
+#[derive(Debug, Clone)]
+pub struct TextureConf{
+    path: String,
+    filter: Filter,
+    wrap: Wrap,
+    mipmaps: bool,
+}
+
+impl TextureConf{
+    /// Defaults to linear filtering, repeat wrapping and mipmaps
+    pub fn new(path: &str) -> Self{
+        Self{
+            path: path.to_string(),
+            filter: Filter::Linear,
+            wrap: Wrap::Repeat,
+            mipmaps: true,
+        }
+    }
+
+    /// Sets filtering for minification and magnification
+    pub fn with_filter(mut self, filter: Filter) -> Self{
+        self.filter = filter;
+        self
+    }
+
+    /// Sets wrapping in both directions
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self{
+        self.wrap = wrap;
+        self
+    }
+
+    /// Generate mipmaps, used when the texture is minified
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self{
+        self.mipmaps = mipmaps;
+        self
+    }
+}
+
+/// Image loaded into a GL texture, reloaded when the file changes
+pub(crate) struct Texture{
+    conf: TextureConf,
+    dirty: Arc<AtomicBool>,
+    id: gl::types::GLuint,
+}
+
+impl Texture{
+    pub fn new(conf: TextureConf) -> Result<Self, Error>{
+        let mut id: gl::types::GLuint = 0;
+        unsafe{
+            gl::GenTextures(1, &mut id);
+        }
+        let texture = Self{ conf, dirty: Arc::new(AtomicBool::new(false)), id };
+        texture.upload()?;
+        Ok(texture)
+    }
+
+    pub fn id(&self) -> gl::types::GLuint{
+        self.id
+    }
+
+    pub fn path(&self) -> &str{
+        &self.conf.path
+    }
+
+    /// Set to true when the file changed
+    pub fn dirty_flag(&self) -> Arc<AtomicBool>{
+        self.dirty.clone()
+    }
+
+    /// Reload the image if the file changed, returns true if it did
+    pub fn reload(&self) -> bool{
+        if !self.dirty.swap(false, Ordering::SeqCst){
+            return false;
+        }
+        println!("Frag: reloading texture {}.", self.conf.path);
+        match self.upload(){
+            Ok(()) => true,
+            Err(e) => {
+                println!("{}", e);
+                false
+            },
+        }
+    }
+
+    fn upload(&self) -> Result<(), Error>{
+        let (w, h, pixels) = load_png(&self.conf.path)?;
+        let filter = match self.conf.filter{
+            Filter::Nearest => gl::NEAREST,
+            Filter::Linear => gl::LINEAR,
+        };
+        let min_filter = match (self.conf.mipmaps, self.conf.filter){
+            (false, _) => filter,
+            (true, Filter::Nearest) => gl::NEAREST_MIPMAP_NEAREST,
+            (true, Filter::Linear) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        let wrap = match self.conf.wrap{
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+        };
+        unsafe{
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D, 0, gl::RGBA8 as i32, w as i32, h as i32, 0,
+                gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const gl::types::GLvoid
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap as i32);
+            if self.conf.mipmaps{
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Texture{
+    fn drop(&mut self){
+        unsafe{
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+/// Decodes any png into 8 bit RGBA, bottom row first like GL expects
+fn load_png(path: &str) -> Result<(u32, u32, Vec<u8>), Error>{
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| Error::image(path, e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| Error::image(path, e))?;
+    let (w, h) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+    let mut pixels = Vec::with_capacity(w * h * 4);
+    for y in (0..h).rev(){
+        let row = &buffer[y * info.line_size..y * info.line_size + w * channels];
+        for px in row.chunks(channels){
+            let rgba = match px{
+                [v] => [*v, *v, *v, 255],
+                [v, a] => [*v, *v, *v, *a],
+                [r, g, b] => [*r, *g, *b, 255],
+                [r, g, b, a] => [*r, *g, *b, *a],
+                _ => return Err(Error::image(path, "unsupported color type")),
+            };
+            pixels.extend_from_slice(&rgba);
+        }
+    }
+    Ok((info.width, info.height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufWriter;
+
+    fn write_png(path: &std::path::Path, color: png::ColorType, w: u32, h: u32, data: &[u8]){
+        let file = File::create(path).unwrap();
+        let mut encoder = png::Encoder::new(BufWriter::new(file), w, h);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(data).unwrap();
+    }
+
+    #[test]
+    fn load_png_rgb_flipped() {
+        let path = crate::test_dir("load_png_rgb_flipped").join("rgb.png");
+        write_png(&path, png::ColorType::Rgb, 1, 2, &[255, 0, 0, 0, 0, 255]);
+        let (w, h, pixels) = load_png(path.to_str().unwrap()).unwrap();
+        assert_eq!((w, h), (1, 2));
+        assert_eq!(pixels, vec![0, 0, 255, 255, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn load_png_grayscale_alpha() {
+        let path = crate::test_dir("load_png_grayscale_alpha").join("ga.png");
+        write_png(&path, png::ColorType::GrayscaleAlpha, 2, 1, &[10, 20, 30, 40]);
+        let (_, _, pixels) = load_png(path.to_str().unwrap()).unwrap();
+        assert_eq!(pixels, vec![10, 10, 10, 20, 30, 30, 30, 40]);
+    }
+
+    #[test]
+    fn load_png_missing() {
+        assert!(matches!(load_png("does/not/exist.png"), Err(Error::Io{ .. })));
+    }
+}
@@ -0,0 +1,120 @@
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::time::Duration;
+use notify::{ EventKind, RecommendedWatcher, RecursiveMode, Watcher as _ };
+use notify_debouncer_full::{ new_debouncer, DebounceEventResult, Debouncer, FileIdMap };
+
+use crate::error::Error;
+
+// events closer together than this are handled as one, like the write and rename of an atomic save
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+type Files = Arc<Mutex<Vec<(PathBuf, Arc<AtomicBool>)>>>;
+
+/// Sets a flag when a file changes.
+/// Watches the parent directory instead of the file itself, so editors that save by writing a
+/// temporary file and renaming it over the original, or delete and recreate the file, are seen too.
+/// Directories are watched non recursively, so a shader next to Cargo.toml doesn't watch target/.
+pub(crate) struct Watcher{
+    debouncer: Debouncer<RecommendedWatcher, FileIdMap>,
+    files: Files,
+    dirs: Vec<PathBuf>,
+}
+
+impl Watcher{
+    pub fn new() -> Result<Self, Error>{
+        let files = Files::default();
+        let flags = files.clone();
+        let debouncer = new_debouncer(DEBOUNCE, None, move |result: DebounceEventResult| {
+            let Ok(events) = result else { return };
+            for event in events.iter().filter(|event| changed(&event.kind)){
+                for (path, flag) in flags.lock().expect("watcher mutex poisoned").iter(){
+                    if event.paths.contains(path) && !flag.swap(true, Ordering::SeqCst){
+                        println!("Frag: marked {:?} dirty.", path);
+                    }
+                }
+            }
+        }).map_err(|source| Error::Watch{ path: String::new(), source })?;
+        Ok(Self{ debouncer, files, dirs: Vec::new() })
+    }
+
+    /// Set the flag whenever the file is modified, created or renamed to
+    pub fn watch(&mut self, file: &str, flag: Arc<AtomicBool>) -> Result<(), Error>{
+        let path = absolute(file)
+            .map_err(|source| Error::Watch{ path: file.to_string(), source: source.into() })?;
+        let dir = path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        self.files.lock().expect("watcher mutex poisoned").push((path, flag));
+        if self.dirs.contains(&dir){
+            return Ok(());
+        }
+        self.debouncer.watcher().watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|source| Error::Watch{ path: file.to_string(), source })?;
+        self.debouncer.cache().add_root(&dir, RecursiveMode::NonRecursive);
+        self.dirs.push(dir);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Watcher{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        f.debug_struct("Watcher").field("dirs", &self.dirs).finish()
+    }
+}
+
+// removing is ignored, the file is usually back soon and building without it would fail
+fn changed(kind: &EventKind) -> bool{
+    matches!(kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any)
+}
+
+// the path like the watcher reports it, the file itself does not have to exist
+fn absolute(file: &str) -> std::io::Result<PathBuf>{
+    let path = Path::new(file);
+    let name = path.file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a file"))?;
+    let dir = match path.parent(){
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Ok(dir.canonicalize()?.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(flag: &AtomicBool) -> bool{
+        let start = std::time::Instant::now();
+        while !flag.load(Ordering::SeqCst) && start.elapsed().as_secs() < 5{
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        flag.swap(false, Ordering::SeqCst)
+    }
+
+    #[test]
+    fn atomic_save_and_recreate() {
+        let dir = crate::test_dir("atomic_save_and_recreate");
+        let file = dir.join("shader.glsl");
+        let other = dir.join("other.glsl");
+        std::fs::write(&file, "a").unwrap();
+        std::fs::write(&other, "a").unwrap();
+        let (flag, other_flag) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+        let mut watcher = Watcher::new().unwrap();
+        watcher.watch(file.to_str().unwrap(), flag.clone()).unwrap();
+        watcher.watch(other.to_str().unwrap(), other_flag.clone()).unwrap();
+        // write a temporary file and rename it over the original
+        let tmp = dir.join("shader.glsl.tmp");
+        std::fs::write(&tmp, "b").unwrap();
+        std::fs::rename(&tmp, &file).unwrap();
+        assert!(wait(&flag));
+        // delete and create again
+        std::fs::remove_file(&file).unwrap();
+        std::thread::sleep(DEBOUNCE * 2);
+        std::fs::write(&file, "c").unwrap();
+        assert!(wait(&flag));
+        // and a plain write still works after the original inode is gone
+        std::fs::write(&file, "d").unwrap();
+        assert!(wait(&flag));
+        assert!(!other_flag.load(Ordering::SeqCst));
+    }
+}